@@ -0,0 +1,68 @@
+# 32-bit integer arithmetic for SPARK-16
+#
+# A 32-bit value is held in a register pair, low word first: A = R2:R1, B = R4:R3.
+# Results are returned in R2:R1. Routines are called with CALL and only clobber
+# the registers they list. Append this file to a program to use it.
+
+# add32: A = A + B
+add32:
+    ADD  R1, R1, R3
+    ADC  R2, R2, R4
+    RET
+
+# sub32: A = A - B
+sub32:
+    SUB  R1, R1, R3
+    SBC  R2, R2, R4
+    RET
+
+# neg32: A = -A
+neg32:
+    SUB  R1, R0, R1
+    SBC  R2, R0, R2
+    RET
+
+# cmp32: compares A with B, the flags are set as by CMP on 32-bit values, so any
+# conditional jump can follow
+cmp32:
+    CMP  R1, R3
+    CMPC R2, R4
+    RET
+
+# mul32: A = A * B, low 32 bits of the product, the same for signed and unsigned
+# values. Clobbers R5.
+mul32:
+    MUL  R2, R3         # A.high * B.low
+    ADD  R5, R4, R0
+    MUL  R5, R1         # B.high * A.low
+    ADD  R2, R2, R5
+    ADD  R5, R1, R0
+    MULHU R5, R3        # carry of A.low * B.low into the high word
+    ADD  R2, R2, R5
+    MUL  R1, R3
+    RET
+
+# udiv32: A = A / B unsigned, the remainder is returned in R6:R5.
+# Division by zero gives 0xFFFFFFFF with the dividend as remainder. Clobbers R7.
+udiv32:
+    XOR  R5, R5, R5
+    XOR  R6, R6, R6
+    XOR  R7, R7, R7
+    ADDI R7, 32
+udiv32_loop:
+    ADD  R1, R1, R1     # shift the dividend into the remainder, one bit at a time
+    ADC  R2, R2, R2
+    ADC  R5, R5, R5
+    ADC  R6, R6, R6
+    JC   udiv32_sub     # 33-bit remainder is above any divisor
+    CMP  R5, R3
+    CMPC R6, R4
+    JC   udiv32_next
+udiv32_sub:
+    SUB  R5, R5, R3
+    SBC  R6, R6, R4
+    ADDI R1, 1          # quotient bit
+udiv32_next:
+    ADDI R7, -1
+    JNZ  udiv32_loop
+    RET
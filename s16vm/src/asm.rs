@@ -0,0 +1,663 @@
+mod error;
+mod lexer;
+mod parser;
+
+pub use error::{AsmError, AsmErrorKind};
+pub use parser::parse_register;
+
+use std::collections::HashMap;
+
+use crate::cpu::instructions::{
+    register::Register, Instruction, Jump as JumpType, BASE_OFFSET_RANGE,
+};
+use parser::{parse_line, Operand, OperandKind, Statement, StatementKind};
+
+type Result<T> = std::result::Result<T, AsmError>;
+
+// Two-pass assembler.
+//
+// The first pass parses every line, assigns addresses to statements and collects labels.
+// The second pass resolves labels and encodes instructions into little-endian bytes,
+// ready to be passed to `CPU::load_program` at the same `origin`.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>> {
+    let mut labels: HashMap<String, u16> = HashMap::new();
+    let mut statements: Vec<(u16, Statement)> = Vec::new();
+    let mut address = origin as u32;
+
+    // Pass 1
+    for (idx, text) in source.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = parse_line(text, line_no)?;
+
+        for (name, column) in line.labels {
+            if address > 0xFFFF {
+                return Err(AsmError::new(
+                    line_no,
+                    column,
+                    AsmErrorKind::ProgramTooLarge,
+                ));
+            }
+            if labels.insert(name.clone(), address as u16).is_some() {
+                return Err(AsmError::new(
+                    line_no,
+                    column,
+                    AsmErrorKind::DuplicateLabel(name),
+                ));
+            }
+        }
+
+        if let Some(statement) = line.statement {
+            let size = statement.size();
+            if address + size > 0x10000 {
+                return Err(AsmError::new(
+                    statement.line,
+                    statement.column,
+                    AsmErrorKind::ProgramTooLarge,
+                ));
+            }
+
+            statements.push((address as u16, statement));
+            address += size;
+        }
+    }
+
+    // Pass 2
+    let mut bytes = Vec::with_capacity((address - origin as u32) as usize);
+    for (address, statement) in statements.iter() {
+        let ops = Operands {
+            labels: &labels,
+            line: statement.line,
+            column: statement.column,
+            address: *address,
+        };
+
+        match &statement.kind {
+            StatementKind::Instruction { mnemonic, operands } => {
+                let instruction = ops.instruction(mnemonic, operands)?;
+                let word = instruction.encode().map_err(|err| {
+                    AsmError::new(
+                        statement.line,
+                        statement.column,
+                        AsmErrorKind::Encoding(err),
+                    )
+                })?;
+                bytes.extend_from_slice(&word.to_bits().to_le_bytes());
+            }
+            StatementKind::Word(values) => {
+                for value in values.iter() {
+                    bytes.extend_from_slice(&ops.word(value)?.to_le_bytes());
+                }
+            }
+            StatementKind::Byte(values) => {
+                for value in values.iter() {
+                    bytes.push(ops.byte(value)?);
+                }
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+// Operand resolution for a single statement located at `address`
+struct Operands<'a> {
+    labels: &'a HashMap<String, u16>,
+    line: usize,
+    column: usize,
+    address: u16,
+}
+
+impl Operands<'_> {
+    fn instruction(&self, mnemonic: &str, ops: &[Operand]) -> Result<Instruction> {
+        use Instruction::*;
+
+        let instruction = match mnemonic.to_ascii_uppercase().as_str() {
+            "ADD" => self.rrr(ops, |rd, rs, rt| Add { rd, rs, rt })?,
+            "SUB" => self.rrr(ops, |rd, rs, rt| Sub { rd, rs, rt })?,
+            "AND" => self.rrr(ops, |rd, rs, rt| And { rd, rs, rt })?,
+            "OR" => self.rrr(ops, |rd, rs, rt| Or { rd, rs, rt })?,
+            "XOR" => self.rrr(ops, |rd, rs, rt| Xor { rd, rs, rt })?,
+            "SLL" => self.rrr(ops, |rd, rs, rt| Sll { rd, rs, rt })?,
+            "SHR" => self.rrr(ops, |rd, rs, rt| Shr { rd, rs, rt })?,
+            "ADC" => self.rrr(ops, |rd, rs, rt| AddWithCarry { rd, rs, rt })?,
+            "SBC" => self.rrr(ops, |rd, rs, rt| SubWithBorrow { rd, rs, rt })?,
+            "NOT" => self.rr(ops, |rd, rt| Not { rd, rt })?,
+            "LOADI" => {
+                self.count(ops, 2)?;
+                LoadIndirect {
+                    rd: self.gpr(&ops[0])?,
+                    rs: self.gpr(&ops[1])?,
+                }
+            }
+            "STOREI" => {
+                self.count(ops, 2)?;
+                StoreIndirect {
+                    rd: self.gpr(&ops[0])?,
+                    rs: self.gpr(&ops[1])?,
+                }
+            }
+            "CMP" => {
+                self.count(ops, 2)?;
+                Cmp {
+                    rs: self.gpr(&ops[0])?,
+                    rt: self.gpr(&ops[1])?,
+                }
+            }
+            "CMPC" => self.rr(ops, |rs, rt| CmpWithBorrow { rs, rt })?,
+            "RET" => {
+                self.count(ops, 0)?;
+                Return
+            }
+            "PUSH" => {
+                self.count(ops, 1)?;
+                Push {
+                    rs: self.gpr(&ops[0])?,
+                }
+            }
+            "POP" => {
+                self.count(ops, 1)?;
+                Pop {
+                    rd: self.gpr(&ops[0])?,
+                }
+            }
+
+            "LOAD" => {
+                self.count(ops, 2)?;
+                Load {
+                    rt: self.gpr(&ops[0])?,
+                    addr: self.unsigned(&ops[1], true)?,
+                }
+            }
+            "STORE" => {
+                self.count(ops, 2)?;
+                Store {
+                    rt: self.gpr(&ops[0])?,
+                    addr: self.unsigned(&ops[1], true)?,
+                }
+            }
+            "LOADO" => self.based(ops, |rt, rs, offset| LoadOffset { rt, rs, offset })?,
+            "STOREO" => self.based(ops, |rt, rs, offset| StoreOffset { rt, rs, offset })?,
+            "ADDI" => {
+                self.count(ops, 2)?;
+                AddImmediate {
+                    rt: self.gpr(&ops[0])?,
+                    imm: self.signed(&ops[1])?,
+                }
+            }
+            "ANDI" => {
+                self.count(ops, 2)?;
+                AndImmediate {
+                    rt: self.gpr(&ops[0])?,
+                    imm: self.unsigned(&ops[1], false)?,
+                }
+            }
+            "ORI" => {
+                self.count(ops, 2)?;
+                OrImmediate {
+                    rt: self.gpr(&ops[0])?,
+                    imm: self.unsigned(&ops[1], false)?,
+                }
+            }
+            "LUI" => {
+                self.count(ops, 2)?;
+                LoadUperImmediate {
+                    rt: self.gpr(&ops[0])?,
+                    imm: self.unsigned(&ops[1], false)?,
+                }
+            }
+            "CMPI" => {
+                self.count(ops, 2)?;
+                CmpImmediate {
+                    rt: self.gpr(&ops[0])?,
+                    imm: self.signed(&ops[1])?,
+                }
+            }
+
+            "CALL" => self.jump(ops, JumpType::Call)?,
+            "JMP" => self.jump(ops, JumpType::Unconditional)?,
+            "JZ" => self.jump(ops, JumpType::Zero)?,
+            "JNZ" => self.jump(ops, JumpType::NotZero)?,
+            "JGT" => self.jump(ops, JumpType::GreaterThan)?,
+            "JLT" => self.jump(ops, JumpType::LessThan)?,
+            "JGE" => self.jump(ops, JumpType::GreaterOrEqual)?,
+            "JLE" => self.jump(ops, JumpType::LessOrEqual)?,
+            "JC" | "JB" => self.jump(ops, JumpType::Carry)?,
+            "JNC" => self.jump(ops, JumpType::NotCarry)?,
+            "JN" => self.jump(ops, JumpType::Negative)?,
+            "JV" => self.jump(ops, JumpType::Overflow)?,
+            "JA" => self.jump(ops, JumpType::Above)?,
+            "JR" => {
+                self.count(ops, 1)?;
+                JumpRegister {
+                    rs: self.gpr(&ops[0])?,
+                }
+            }
+            "CALLR" => {
+                self.count(ops, 1)?;
+                CallRegister {
+                    rs: self.gpr(&ops[0])?,
+                }
+            }
+
+            // MOVS Rt, SPEC or MOVS SPEC, Rs
+            "MOVS" => {
+                self.count(ops, 2)?;
+                match (&ops[0].kind, &ops[1].kind) {
+                    (OperandKind::Register(spec), _) if spec.is_special() => MoveFromToSpecial {
+                        rt: self.gpr(&ops[1])?,
+                        spec: *spec,
+                    },
+                    _ => MoveFromSpecial {
+                        rt: self.gpr(&ops[0])?,
+                        spec: self.special(&ops[1])?,
+                    },
+                }
+            }
+
+            "MUL" => self.rr(ops, |rd, rs| Mul { rd, rs })?,
+            "MULH" => self.rr(ops, |rd, rs| MulHigh { rd, rs })?,
+            "MULHU" => self.rr(ops, |rd, rs| MulHighUnsigned { rd, rs })?,
+            "DIV" => self.rr(ops, |rd, rs| Div { rd, rs })?,
+            "DIVU" => self.rr(ops, |rd, rs| DivUnsigned { rd, rs })?,
+            "REM" => self.rr(ops, |rd, rs| Rem { rd, rs })?,
+            "REMU" => self.rr(ops, |rd, rs| RemUnsigned { rd, rs })?,
+            "SRA" => self.rr(ops, |rd, rs| ShiftRightArithmetic { rd, rs })?,
+            "ROL" => self.rr(ops, |rd, rs| RotateLeft { rd, rs })?,
+            "ROR" => self.rr(ops, |rd, rs| RotateRight { rd, rs })?,
+            "RCL" => self.rr(ops, |rd, rs| RotateLeftCarry { rd, rs })?,
+            "RCR" => self.rr(ops, |rd, rs| RotateRightCarry { rd, rs })?,
+            "LOADB" => self.rr(ops, |rd, rs| LoadByte { rd, rs })?,
+            "LOADBU" => self.rr(ops, |rd, rs| LoadByteUnsigned { rd, rs })?,
+            "STOREB" => self.rr(ops, |rd, rs| StoreByte { rd, rs })?,
+
+            "NOP" => {
+                self.count(ops, 0)?;
+                Nop
+            }
+            "RETI" => {
+                self.count(ops, 0)?;
+                ReturnFromInterrupt
+            }
+            "SYSCALL" => {
+                self.count(ops, 0)?;
+                Sysall
+            }
+            "HALT" => {
+                self.count(ops, 0)?;
+                Halt
+            }
+
+            _ => {
+                return Err(self.error(
+                    self.column,
+                    AsmErrorKind::UnknownMnemonic(mnemonic.to_string()),
+                ))
+            }
+        };
+
+        Ok(instruction)
+    }
+
+    // Raw data: a number in [-32768, 65535] or an absolute label address
+    fn word(&self, op: &Operand) -> Result<u16> {
+        match &op.kind {
+            OperandKind::Number(value) => {
+                self.check_range(op, *value, i16::MIN as i32, u16::MAX as i32)?;
+                Ok(*value as u16)
+            }
+            OperandKind::Label(name) => self.label(op, name),
+            OperandKind::Register(_) | OperandKind::Memory { .. } => {
+                Err(self.error(op.column, AsmErrorKind::ExpectedImmediate))
+            }
+        }
+    }
+
+    fn byte(&self, op: &Operand) -> Result<u8> {
+        let value = self.number(op)?;
+        self.check_range(op, value, i8::MIN as i32, u8::MAX as i32)?;
+        Ok(value as u8)
+    }
+
+    fn rrr(
+        &self,
+        ops: &[Operand],
+        build: impl Fn(Register, Register, Register) -> Instruction,
+    ) -> Result<Instruction> {
+        self.count(ops, 3)?;
+        Ok(build(
+            self.gpr(&ops[0])?,
+            self.gpr(&ops[1])?,
+            self.gpr(&ops[2])?,
+        ))
+    }
+
+    fn rr(
+        &self,
+        ops: &[Operand],
+        build: impl Fn(Register, Register) -> Instruction,
+    ) -> Result<Instruction> {
+        self.count(ops, 2)?;
+        Ok(build(self.gpr(&ops[0])?, self.gpr(&ops[1])?))
+    }
+
+    // Rt, [Rs+offset]
+    fn based(
+        &self,
+        ops: &[Operand],
+        build: impl Fn(Register, Register, i8) -> Instruction,
+    ) -> Result<Instruction> {
+        self.count(ops, 2)?;
+        let rt = self.gpr(&ops[0])?;
+        let (rs, offset) = self.memory(&ops[1])?;
+        Ok(build(rt, rs, offset))
+    }
+
+    // Labels are converted to an offset relative to the next instruction,
+    // since PC is already incremented when the jump executes.
+    // Plain numbers are used as the raw offset.
+    fn jump(&self, ops: &[Operand], jump_type: JumpType) -> Result<Instruction> {
+        self.count(ops, 1)?;
+        let op = &ops[0];
+
+        let offset = match &op.kind {
+            OperandKind::Number(value) => *value,
+            OperandKind::Label(name) => self.label(op, name)? as i32 - (self.address as i32 + 2),
+            OperandKind::Register(_) | OperandKind::Memory { .. } => {
+                return Err(self.error(op.column, AsmErrorKind::ExpectedImmediate))
+            }
+        };
+
+        let range = jump_type.offset_range();
+        let (min, max) = (*range.start() as i32, *range.end() as i32);
+        if offset < min || offset > max {
+            return Err(self.error(
+                op.column,
+                AsmErrorKind::OffsetOutOfRange { offset, min, max },
+            ));
+        }
+
+        Ok(Instruction::Jump {
+            jump_type,
+            offset: (offset as u16) & 0x0FFF,
+        })
+    }
+
+    fn count(&self, ops: &[Operand], expected: usize) -> Result<()> {
+        if ops.len() != expected {
+            let column = ops.get(expected).map_or(self.column, |op| op.column);
+            return Err(self.error(
+                column,
+                AsmErrorKind::OperandCount {
+                    expected,
+                    found: ops.len(),
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    fn gpr(&self, op: &Operand) -> Result<Register> {
+        match op.kind {
+            OperandKind::Register(reg) if !reg.is_special() => Ok(reg),
+            _ => Err(self.error(op.column, AsmErrorKind::ExpectedRegister)),
+        }
+    }
+
+    fn special(&self, op: &Operand) -> Result<Register> {
+        match op.kind {
+            OperandKind::Register(reg) if reg.is_special() => Ok(reg),
+            _ => Err(self.error(op.column, AsmErrorKind::ExpectedSpecialRegister)),
+        }
+    }
+
+    // The base is a general purpose register, the offset is signed and fits in 5 bits
+    fn memory(&self, op: &Operand) -> Result<(Register, i8)> {
+        match op.kind {
+            OperandKind::Memory { base, offset } if !base.is_special() => {
+                let (min, max) = (*BASE_OFFSET_RANGE.start(), *BASE_OFFSET_RANGE.end());
+                self.check_range(op, offset, min as i32, max as i32)?;
+                Ok((base, offset as i8))
+            }
+            OperandKind::Memory { .. } => {
+                Err(self.error(op.column, AsmErrorKind::ExpectedRegister))
+            }
+            _ => Err(self.error(op.column, AsmErrorKind::ExpectedMemoryOperand)),
+        }
+    }
+
+    fn signed(&self, op: &Operand) -> Result<i8> {
+        let value = self.number(op)?;
+        self.check_range(op, value, i8::MIN as i32, i8::MAX as i32)?;
+        Ok(value as i8)
+    }
+
+    // Addresses may also be given as a label, as long as it's within the first 256 bytes
+    fn unsigned(&self, op: &Operand, allow_label: bool) -> Result<u8> {
+        let value = match &op.kind {
+            OperandKind::Label(name) if allow_label => self.label(op, name)? as i32,
+            _ => self.number(op)?,
+        };
+        self.check_range(op, value, 0, u8::MAX as i32)?;
+        Ok(value as u8)
+    }
+
+    fn number(&self, op: &Operand) -> Result<i32> {
+        match op.kind {
+            OperandKind::Number(value) => Ok(value),
+            _ => Err(self.error(op.column, AsmErrorKind::ExpectedImmediate)),
+        }
+    }
+
+    fn label(&self, op: &Operand, name: &str) -> Result<u16> {
+        self.labels
+            .get(name)
+            .copied()
+            .ok_or_else(|| self.error(op.column, AsmErrorKind::UndefinedLabel(name.to_string())))
+    }
+
+    fn check_range(&self, op: &Operand, value: i32, min: i32, max: i32) -> Result<()> {
+        if value < min || value > max {
+            return Err(self.error(
+                op.column,
+                AsmErrorKind::ImmediateOutOfRange { value, min, max },
+            ));
+        }
+        Ok(())
+    }
+
+    fn error(&self, column: usize, kind: AsmErrorKind) -> AsmError {
+        AsmError::new(self.line, column, kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::instructions::word::Word;
+
+    fn words(bytes: &[u8]) -> Vec<u16> {
+        bytes
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn test_assemble_instructions() {
+        let src = "
+            ADDI R1, 5          # comment
+            add  r3, r1, r2
+            STORE R3, 0x1F
+            MOVS R1, SP
+            MOVS FLAGS, R2
+            HALT
+        ";
+        let bytes = assemble(src, 0).unwrap();
+
+        let expected = [
+            Word::IType { opcode: 0x4, rt: 0x1, mode: 0x0, imm: 0x05 },
+            Word::RType { opcode: 0x0, rd: 0x3, rs: 0x1, rt: 0x2, funct: 0x0 },
+            Word::IType { opcode: 0x3, rt: 0x3, mode: 0x0, imm: 0x1F },
+            Word::EType { subcode: 0x1, rs: 0x1, rt: 0x1, funct: 0x0 },
+            Word::EType { subcode: 0x2, rs: 0x2, rt: 0x2, funct: 0x0 },
+            Word::EType { subcode: 0xF, rs: 0x0, rt: 0x0, funct: 0x0 },
+        ];
+        let expected: Vec<u16> = expected.iter().map(|w| w.to_bits()).collect();
+
+        assert_eq!(words(&bytes), expected);
+    }
+
+    #[test]
+    fn test_labels() {
+        let src = "
+        start:
+            JMP end         # forward reference
+        loop: ADDI R1, -1
+            JNZ loop        # backward reference
+        end:
+            CALL start
+            .word end, -1, 0xBEEF
+            .byte 0x12, -1
+        ";
+        let bytes = assemble(src, 0x100).unwrap();
+        let words = words(&bytes);
+
+        assert_eq!(words[0], 0xA004); // 0x102 + 4 = 0x106
+        assert_eq!(words[2], 0xCFFC); // 0x106 - 4 = 0x102
+        assert_eq!(words[3], 0x9FF8); // 0x108 - 8 = 0x100
+        assert_eq!(&words[4..], &[0x0106, 0xFFFF, 0xBEEF, 0xFF12]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = assemble("ADDI R1, 5\n  ADDI R1, 200", 0).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 12));
+        assert!(matches!(
+            err.kind,
+            AsmErrorKind::ImmediateOutOfRange { value: 200, min: -128, max: 127 }
+        ));
+
+        let bytes = assemble("JMP far\n.word 0\nfar: HALT", 0x0).unwrap();
+        assert_eq!(words(&bytes)[0], 0xA002);
+
+        let src = format!("JMP far\n{}far: HALT", ".word 0\n".repeat(2048));
+        let err = assemble(&src, 0).unwrap_err();
+        assert_eq!((err.line, err.column), (1, 5));
+        assert!(matches!(
+            err.kind,
+            AsmErrorKind::OffsetOutOfRange { offset: 4096, .. }
+        ));
+
+        let src = format!("JLT far\n{}far: HALT", ".word 0\n".repeat(128));
+        let err = assemble(&src, 0).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            AsmErrorKind::OffsetOutOfRange {
+                offset: 256,
+                min: -256,
+                max: 255
+            }
+        ));
+
+        let err = assemble("JZ nowhere", 0).unwrap_err();
+        assert!(matches!(err.kind, AsmErrorKind::UndefinedLabel(_)));
+
+        let err = assemble("a: NOP\na: NOP", 0).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+        assert!(matches!(err.kind, AsmErrorKind::DuplicateLabel(_)));
+
+        let err = assemble("ADD R1, R2", 0).unwrap_err();
+        assert!(matches!(err.kind, AsmErrorKind::OperandCount { expected: 3, found: 2 }));
+
+        let err = assemble("PUSH SP", 0).unwrap_err();
+        assert!(matches!(err.kind, AsmErrorKind::ExpectedRegister));
+
+        let err = assemble("FOO R1", 0).unwrap_err();
+        assert!(matches!(err.kind, AsmErrorKind::UnknownMnemonic(_)));
+    }
+
+    #[test]
+    fn test_memory_operands() {
+        let src = "
+            LOADO  R1, [R2+4]
+            LOADO  R1, [ r2 + 0xF ]
+            LOADO  R1, [R2-16]
+            STOREO R3, [R7]
+            ADDI   R1, +5
+        ";
+        let words = words(&assemble(src, 0).unwrap());
+        assert_eq!(words, [0x2344, 0x234F, 0x2350, 0x37E0, 0x4205]);
+
+        for (src, column) in [
+            ("LOADO R1, [R2+16]", 11),
+            ("LOADO R1, [R2-17]", 11),
+            ("LOADO R1, [SP+2]", 11),
+            ("LOADO R1, R2", 11),
+            ("LOADO R1, [R2+]", 15),
+            ("LOADO R1, [R2+4", 16),
+            ("STOREO R1, [R2 4]", 16),
+        ] {
+            let err = assemble(src, 0).unwrap_err();
+            assert_eq!((err.line, err.column), (1, column), "{}", src);
+        }
+
+        let err = assemble("LOADO R1, [R2-17]", 0).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            AsmErrorKind::ImmediateOutOfRange {
+                value: -17,
+                min: -16,
+                max: 15
+            }
+        ));
+        let err = assemble("LOADO R1, R2", 0).unwrap_err();
+        assert!(matches!(err.kind, AsmErrorKind::ExpectedMemoryOperand));
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let src = "
+            ADD R1, R2, R3
+            SUB R1, R2, R3
+            AND R1, R2, R3
+            OR R1, R2, R3
+            XOR R1, R2, R3
+            NOT R4, R5
+            SLL R1, R2, R3
+            SHR R1, R2, R3
+            LOADI R1, R2
+            STOREI R1, R2
+            CMP R1, R2
+            RET
+            PUSH R6
+            POP R7
+            LOAD R1, 0xFF
+            STORE R1, 0
+            LOADO R1, [R2-16]
+            LOADO R1, [R2+15]
+            STOREO R7, [R0]
+            ADDI R1, -128
+            ANDI R1, 255
+            ORI R1, 1
+            LUI R1, 0x80
+            CMPI R1, 127
+            CALL -2048
+            JMP 2047
+            JZ 0
+            JNZ 2
+            JGT -2
+            MOVS R3, PC
+            MOVS SP, R3
+            NOP
+            RETI
+            SYSCALL
+            HALT
+        ";
+        let bytes = assemble(src, 0).unwrap();
+
+        for bits in words(&bytes) {
+            let instruction = Instruction::decode(Word::new(bits)).unwrap();
+            assert_eq!(instruction.encode().unwrap().to_bits(), bits);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::cpu::instructions::error::InstructionError;
+
+#[derive(Debug)]
+pub enum AsmErrorKind {
+    UnexpectedCharacter(char),
+    InvalidNumber(String),
+    UnexpectedToken(String),
+    UnknownMnemonic(String),
+    UnknownDirective(String),
+    OperandCount { expected: usize, found: usize },
+    ExpectedOperand,
+    ExpectedRegister,
+    ExpectedSpecialRegister,
+    ExpectedImmediate,
+    ExpectedMemoryOperand,
+    ImmediateOutOfRange { value: i32, min: i32, max: i32 },
+    OffsetOutOfRange { offset: i32, min: i32, max: i32 },
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    ProgramTooLarge,
+    Encoding(InstructionError),
+}
+
+// Every assembler error points to the place in the source where it happened.
+// Lines and columns are 1-based.
+#[derive(Debug)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl AsmError {
+    pub fn new(line: usize, column: usize, kind: AsmErrorKind) -> Self {
+        Self { line, column, kind }
+    }
+}
+
+impl std::fmt::Display for AsmErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use AsmErrorKind::*;
+        match self {
+            UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            InvalidNumber(s) => write!(f, "invalid number '{}'", s),
+            UnexpectedToken(s) => write!(f, "unexpected '{}'", s),
+            UnknownMnemonic(s) => write!(f, "unknown mnemonic '{}'", s),
+            UnknownDirective(s) => write!(f, "unknown directive '{}'", s),
+            OperandCount { expected, found } => {
+                write!(f, "expected {} operand(s), found {}", expected, found)
+            }
+            ExpectedOperand => write!(f, "expected operand"),
+            ExpectedRegister => write!(f, "expected general purpose register R0-R7"),
+            ExpectedSpecialRegister => write!(f, "expected special register PC, SP, FLAGS or USP"),
+            ExpectedImmediate => write!(f, "expected immediate value"),
+            ExpectedMemoryOperand => write!(f, "expected memory operand [Rs+offset]"),
+            ImmediateOutOfRange { value, min, max } => {
+                write!(f, "immediate {} out of range [{}, {}]", value, min, max)
+            }
+            OffsetOutOfRange { offset, min, max } => {
+                write!(f, "jump offset {} out of range [{}, {}]", offset, min, max)
+            }
+            UndefinedLabel(s) => write!(f, "undefined label '{}'", s),
+            DuplicateLabel(s) => write!(f, "label '{}' is already defined", s),
+            ProgramTooLarge => write!(f, "program does not fit into 64KB address space"),
+            Encoding(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for AsmError {}
@@ -0,0 +1,115 @@
+use super::error::{AsmError, AsmErrorKind};
+
+type Result<T> = std::result::Result<T, AsmError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Ident(String),
+    Number(i32),
+    Comma,
+    Colon,
+    Plus,
+    LeftBracket,
+    RightBracket,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub column: usize,
+}
+
+// Splits a single source line into tokens. Everything after `#` is a comment.
+pub fn tokenize(line: &str, line_no: usize) -> Result<Vec<Token>> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        match c {
+            '#' => break,
+            c if c.is_whitespace() => i += 1,
+            ',' => {
+                tokens.push(Token {
+                    kind: TokenKind::Comma,
+                    column,
+                });
+                i += 1;
+            }
+            ':' | '+' | '[' | ']' => {
+                let kind = match c {
+                    ':' => TokenKind::Colon,
+                    '+' => TokenKind::Plus,
+                    '[' => TokenKind::LeftBracket,
+                    _ => TokenKind::RightBracket,
+                };
+                tokens.push(Token { kind, column });
+                i += 1;
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+
+                let text: String = chars[start..i].iter().collect();
+                let value = parse_number(&text).ok_or_else(|| {
+                    AsmError::new(line_no, column, AsmErrorKind::InvalidNumber(text.clone()))
+                })?;
+                tokens.push(Token {
+                    kind: TokenKind::Number(value),
+                    column,
+                });
+            }
+            c if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+                {
+                    i += 1;
+                }
+
+                let text: String = chars[start..i].iter().collect();
+                tokens.push(Token {
+                    kind: TokenKind::Ident(text),
+                    column,
+                });
+            }
+            _ => {
+                return Err(AsmError::new(
+                    line_no,
+                    column,
+                    AsmErrorKind::UnexpectedCharacter(c),
+                ))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Supports decimal, 0x hexadecimal and 0b binary literals with an optional minus sign,
+// a leading `+` is a separate token. Underscores can be used as separators: 0b0101_1111.
+fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let digits = digits.replace('_', "");
+    let lower = digits.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        lower.parse::<i64>().ok()?
+    };
+
+    let value = if negative { -value } else { value };
+    i32::try_from(value).ok()
+}
@@ -0,0 +1,230 @@
+use super::{
+    error::{AsmError, AsmErrorKind},
+    lexer::{tokenize, Token, TokenKind},
+};
+use crate::cpu::instructions::register::Register;
+
+type Result<T> = std::result::Result<T, AsmError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperandKind {
+    Register(Register),
+    Number(i32),
+    Label(String),
+    Memory { base: Register, offset: i32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Operand {
+    pub kind: OperandKind,
+    pub column: usize,
+}
+
+#[derive(Debug)]
+pub enum StatementKind {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    Word(Vec<Operand>),
+    Byte(Vec<Operand>),
+}
+
+#[derive(Debug)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Statement {
+    // Number of bytes the statement occupies in the output
+    pub fn size(&self) -> u32 {
+        match &self.kind {
+            StatementKind::Instruction { .. } => 2,
+            StatementKind::Word(values) => 2 * values.len() as u32,
+            StatementKind::Byte(values) => values.len() as u32,
+        }
+    }
+}
+
+// A source line is any number of `label:` definitions followed by an optional statement:
+//
+//     loop: ADDI R1, -1   # comment
+#[derive(Debug, Default)]
+pub struct Line {
+    pub labels: Vec<(String, usize)>,
+    pub statement: Option<Statement>,
+}
+
+pub fn parse_line(source: &str, line_no: usize) -> Result<Line> {
+    let tokens = tokenize(source, line_no)?;
+    let mut line = Line::default();
+    let mut pos = 0;
+
+    // Labels
+    while let (
+        Some(Token {
+            kind: TokenKind::Ident(name),
+            column,
+        }),
+        Some(Token {
+            kind: TokenKind::Colon,
+            ..
+        }),
+    ) = (tokens.get(pos), tokens.get(pos + 1))
+    {
+        line.labels.push((name.clone(), *column));
+        pos += 2;
+    }
+
+    let Some(head) = tokens.get(pos) else {
+        return Ok(line);
+    };
+
+    let name = match &head.kind {
+        TokenKind::Ident(name) => name.clone(),
+        other => return Err(unexpected(line_no, head.column, other)),
+    };
+    let operands = parse_operands(&tokens[pos + 1..], line_no, head.column + name.len())?;
+
+    let kind = if name.starts_with('.') {
+        match name.to_ascii_lowercase().as_str() {
+            ".word" => StatementKind::Word(operands),
+            ".byte" => StatementKind::Byte(operands),
+            _ => {
+                return Err(AsmError::new(
+                    line_no,
+                    head.column,
+                    AsmErrorKind::UnknownDirective(name),
+                ))
+            }
+        }
+    } else {
+        StatementKind::Instruction {
+            mnemonic: name,
+            operands,
+        }
+    };
+
+    line.statement = Some(Statement {
+        kind,
+        line: line_no,
+        column: head.column,
+    });
+
+    Ok(line)
+}
+
+// Comma separated list of registers, numbers, labels and `[Rs+offset]` memory operands
+fn parse_operands(tokens: &[Token], line_no: usize, end_column: usize) -> Result<Vec<Operand>> {
+    let mut operands = Vec::new();
+    let mut iter = tokens.iter().peekable();
+    let end_column = tokens.last().map_or(end_column, |t| t.column + 1);
+    let expected_operand = || AsmError::new(line_no, end_column, AsmErrorKind::ExpectedOperand);
+
+    if iter.peek().is_none() {
+        return Ok(operands);
+    }
+
+    loop {
+        let token = iter.next().ok_or_else(expected_operand)?;
+
+        let kind = match &token.kind {
+            TokenKind::Number(value) => OperandKind::Number(*value),
+            TokenKind::Plus => match iter.next().ok_or_else(expected_operand)? {
+                Token {
+                    kind: TokenKind::Number(value),
+                    ..
+                } => OperandKind::Number(*value),
+                token => return Err(unexpected(line_no, token.column, &token.kind)),
+            },
+            TokenKind::LeftBracket => {
+                let mut next = || iter.next().ok_or_else(expected_operand);
+
+                let token = next()?;
+                let base = match &token.kind {
+                    TokenKind::Ident(name) => parse_register(name),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    AsmError::new(line_no, token.column, AsmErrorKind::ExpectedRegister)
+                })?;
+
+                // `+offset`, or `-offset` lexed as a negative number, is optional
+                let mut token = next()?;
+                let mut offset = 0;
+                if let TokenKind::Number(value @ ..=-1) = token.kind {
+                    offset = value;
+                    token = next()?;
+                } else if token.kind == TokenKind::Plus {
+                    token = next()?;
+                    match token.kind {
+                        TokenKind::Number(value) => offset = value,
+                        _ => return Err(unexpected(line_no, token.column, &token.kind)),
+                    }
+                    token = next()?;
+                }
+
+                if token.kind != TokenKind::RightBracket {
+                    return Err(unexpected(line_no, token.column, &token.kind));
+                }
+                OperandKind::Memory { base, offset }
+            }
+            TokenKind::Ident(name) => match parse_register(name) {
+                Some(reg) => OperandKind::Register(reg),
+                None => OperandKind::Label(name.clone()),
+            },
+            other => return Err(unexpected(line_no, token.column, other)),
+        };
+        operands.push(Operand {
+            kind,
+            column: token.column,
+        });
+
+        match iter.next() {
+            None => break,
+            Some(Token {
+                kind: TokenKind::Comma,
+                ..
+            }) => continue,
+            Some(token) => return Err(unexpected(line_no, token.column, &token.kind)),
+        }
+    }
+
+    Ok(operands)
+}
+
+pub fn parse_register(name: &str) -> Option<Register> {
+    let reg = match name.to_ascii_uppercase().as_str() {
+        "R0" => Register::R0,
+        "R1" => Register::R1,
+        "R2" => Register::R2,
+        "R3" => Register::R3,
+        "R4" => Register::R4,
+        "R5" => Register::R5,
+        "R6" => Register::R6,
+        "R7" => Register::R7,
+        "SP" => Register::SP,
+        "PC" => Register::PC,
+        "FLAGS" => Register::FLAGS,
+        "USP" => Register::USP,
+        _ => return None,
+    };
+
+    Some(reg)
+}
+
+fn unexpected(line_no: usize, column: usize, kind: &TokenKind) -> AsmError {
+    let text = match kind {
+        TokenKind::Ident(name) => name.clone(),
+        TokenKind::Number(value) => value.to_string(),
+        TokenKind::Comma => ",".to_string(),
+        TokenKind::Colon => ":".to_string(),
+        TokenKind::Plus => "+".to_string(),
+        TokenKind::LeftBracket => "[".to_string(),
+        TokenKind::RightBracket => "]".to_string(),
+    };
+
+    AsmError::new(line_no, column, AsmErrorKind::UnexpectedToken(text))
+}
@@ -0,0 +1,709 @@
+use std::path::{Path, PathBuf};
+
+use s16vm::{
+    asm::{self, AsmError},
+    cpu::{
+        cycles::CycleCosts,
+        devices::{console::Console, timer::Timer},
+        error::CpuError,
+        exceptions::ExceptionMode,
+        instructions::isa,
+        memory::Rom,
+        protection::{Permissions, Region, REGION_COUNT},
+        snapshot::SnapshotError,
+        trace::{BinaryTracer, JsonTracer, TextTracer, Tracer},
+        CPU,
+    },
+    debugger::{run_repl, Debugger},
+    disasm, gdb,
+};
+
+pub const USAGE: &str = "\
+Usage:
+    s16vm run <file> [options]      Run a binary (or .s/.asm source) program
+    s16vm resume <snapshot> [options]
+                                    Continue a program saved with --save-snapshot,
+                                    the same devices have to be mapped
+    s16vm debug <file> [options]    Run a program in the interactive debugger
+    s16vm gdb <file> [options]      Serve a program to a GDB remote debugger
+    s16vm asm <src> -o <bin>        Assemble source into a binary
+    s16vm disasm <bin>              Disassemble a binary
+    s16vm help                      Show this message
+
+Options:
+    --load-addr <addr>      Address to load the program at (default 0x0),
+                            also the origin for asm/disasm
+    --entry <addr>          Start execution at <addr> instead of the load address (run, debug, gdb)
+    --max-steps <n>         Stop with an error after <n> instructions (run)
+    --dump-regs             Print registers when the program stops (run)
+    --dump-mem <addr:len>   Print a memory range when the program stops, can be repeated (run)
+    --dump-code <addr:len>  Disassemble a memory range when the program stops, can be repeated (run)
+    --console <addr>        Map a console device at <addr>: byte 0 is data, byte 1 is status (run, debug, gdb)
+    --rom <addr:file>       Map a binary file as read-only memory at <addr>, can be repeated (run, debug, gdb)
+    --timer <addr>          Map a timer device raising interrupt line 0 at <addr> (run, debug, gdb)
+    --cycle-cost <op=n>     Set the cycle cost of an instruction, e.g. ADD=2, can be repeated (run, debug, gdb)
+    --region <start:end:perms>
+                            Add a memory protection region, perms is any of r, w, x, u, e.g. 0x100:0x1FF:rx,
+                            can be repeated up to 8 times, enables memory protection (run, debug, gdb)
+    --exceptions <mode>     fatal (default) stops on faults, trap passes them to the program's
+                            exception handler (run, debug, gdb)
+    --mpu-window <addr>     Expose the memory protection registers to the program at <addr> (run, debug, gdb)
+    --save-snapshot <file>  Save the machine state when the program stops (run)
+    --trace <format>        Trace every instruction as text, json (JSON Lines) or bin (run)
+    --trace-file <file>     Write the trace to <file> instead of stdout (run)
+    --history <n>           Number of steps that can be undone (debug, gdb, default 10000)
+    --port <n>              TCP port on localhost to listen on (gdb, default 1234)
+    --socket <path>         Listen on a Unix socket instead of TCP (gdb)
+    -o <file>               Output file (asm)
+
+resume accepts the same options as run.
+Numbers can be decimal or hexadecimal with 0x prefix.
+
+The exit code is the one the program passed to the exit syscall, 255 if it is larger.
+Errors exit with 1-5 or 10-21 and print a message, a program exiting with one of these
+codes can only be told apart by the missing message.";
+
+pub enum Command {
+    Run(RunOptions),
+    Debug(RunOptions),
+    Gdb {
+        run: RunOptions,
+        listen: Listen,
+    },
+    Asm {
+        source: PathBuf,
+        output: PathBuf,
+        origin: u16,
+    },
+    Disasm {
+        input: PathBuf,
+        origin: u16,
+    },
+    Help,
+}
+
+pub struct RunOptions {
+    pub file: PathBuf,
+    pub load_addr: u16,
+    pub entry: Option<u16>,
+    pub max_steps: Option<u64>,
+    pub dump_registers: bool,
+    pub dump_memory: Vec<(u16, u16)>,
+    pub dump_code: Vec<(u16, u16)>,
+    pub console: Option<u16>,
+    pub roms: Vec<(u16, PathBuf)>,
+    pub timer: Option<u16>,
+    pub cycle_costs: Vec<(String, u32)>,
+    pub regions: Vec<Region>,
+    pub mpu_window: Option<u16>,
+    pub exceptions: ExceptionMode,
+    pub trace: Option<TraceFormat>,
+    pub trace_file: Option<PathBuf>,
+    pub save_snapshot: Option<PathBuf>,
+    pub history: Option<usize>,
+    pub resume: bool, // `file` is a snapshot to restore rather than a program
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Json,
+    Binary,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Listen {
+    Tcp(u16),
+    Unix(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    Io(PathBuf, std::io::Error),
+    Asm(PathBuf, AsmError),
+    Cpu(CpuError),
+    StepLimit(u64),
+    Snapshot(PathBuf, SnapshotError),
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Usage(msg) => write!(f, "{}\n\n{}", msg, USAGE),
+            CliError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            CliError::Asm(path, err) => write!(f, "{}:{}", path.display(), err),
+            CliError::Cpu(err) => write!(f, "cpu error: {}", err),
+            CliError::StepLimit(steps) => write!(f, "step limit of {} instructions reached", steps),
+            CliError::Snapshot(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<CpuError> for CliError {
+    fn from(err: CpuError) -> Self {
+        CliError::Cpu(err)
+    }
+}
+
+impl CliError {
+    // Process exit code on failure.
+    //   1 - I/O error, 2 - usage error, 3 - assembly error, 4 - step limit reached,
+    //   5 - invalid snapshot,
+    //   10+ - CPU errors, one code per CpuError variant
+    // Guest programs can exit with the same codes, see USAGE.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Io(..) => 1,
+            CliError::Usage(_) => 2,
+            CliError::Asm(..) => 3,
+            CliError::StepLimit(_) => 4,
+            CliError::Snapshot(..) => 5,
+            CliError::Cpu(err) => match err {
+                CpuError::InvalidInstruction(..) => 10,
+                CpuError::MemoryOutOfBounds(_) => 11,
+                CpuError::ProgramBoundsViolation { .. } => 12,
+                CpuError::StackOverflow => 13,
+                CpuError::InvalidSyscall(_) => 14,
+                CpuError::HostIo(_) => 15,
+                CpuError::ExecuteViolation(_) => 16,
+                CpuError::WriteViolation(_) => 17,
+                CpuError::ReadViolation(_) => 18,
+                CpuError::PrivilegeViolation(_) => 19,
+                CpuError::DivideByZero(_) => 20,
+                CpuError::ProgramTooLarge { .. } => 21,
+            },
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, CliError>;
+
+pub fn parse_args(args: &[String]) -> Result<Command> {
+    let Some(command) = args.first() else {
+        return Ok(Command::Help);
+    };
+
+    let mut positional = Vec::new();
+    let mut output = None;
+    let mut listen = Listen::Tcp(1234);
+    let mut run = RunOptions {
+        file: PathBuf::new(),
+        load_addr: 0x0,
+        entry: None,
+        max_steps: None,
+        dump_registers: false,
+        dump_memory: Vec::new(),
+        dump_code: Vec::new(),
+        console: None,
+        roms: Vec::new(),
+        timer: None,
+        cycle_costs: Vec::new(),
+        regions: Vec::new(),
+        mpu_window: None,
+        exceptions: ExceptionMode::Fatal,
+        trace: None,
+        trace_file: None,
+        save_snapshot: None,
+        history: None,
+        resume: false,
+    };
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| {
+            iter.next()
+                .ok_or_else(|| CliError::Usage(format!("missing value for {}", name)))
+        };
+
+        match arg.as_str() {
+            "--load-addr" => run.load_addr = parse_u16(value(arg)?)?,
+            "--entry" => run.entry = Some(parse_u16(value(arg)?)?),
+            "--max-steps" => run.max_steps = Some(parse_number(value(arg)?)?),
+            "--dump-regs" => run.dump_registers = true,
+            "--dump-mem" => run.dump_memory.push(parse_range(value(arg)?)?),
+            "--dump-code" => run.dump_code.push(parse_range(value(arg)?)?),
+            "--console" => run.console = Some(parse_u16(value(arg)?)?),
+            "--rom" => run.roms.push(parse_rom(value(arg)?)?),
+            "--timer" => run.timer = Some(parse_u16(value(arg)?)?),
+            "--cycle-cost" => run.cycle_costs.push(parse_cost(value(arg)?)?),
+            "--region" if run.regions.len() == REGION_COUNT => {
+                return Err(CliError::Usage(format!(
+                    "at most {} regions can be given",
+                    REGION_COUNT
+                )))
+            }
+            "--region" => run.regions.push(parse_region(value(arg)?)?),
+            "--mpu-window" => run.mpu_window = Some(parse_u16(value(arg)?)?),
+            "--exceptions" => run.exceptions = parse_exception_mode(value(arg)?)?,
+            "--save-snapshot" => run.save_snapshot = Some(PathBuf::from(value(arg)?)),
+            "--trace" => run.trace = Some(parse_trace_format(value(arg)?)?),
+            "--trace-file" => run.trace_file = Some(PathBuf::from(value(arg)?)),
+            "--history" => run.history = Some(parse_number(value(arg)?)? as usize),
+            "--port" => listen = Listen::Tcp(parse_u16(value(arg)?)?),
+            "--socket" => listen = Listen::Unix(PathBuf::from(value(arg)?)),
+            "-o" => output = Some(PathBuf::from(value(arg)?)),
+            flag if flag.starts_with('-') => {
+                return Err(CliError::Usage(format!("unknown option {}", flag)))
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let single_file = |positional: Vec<PathBuf>| match <[PathBuf; 1]>::try_from(positional) {
+        Ok([file]) => Ok(file),
+        Err(_) => Err(CliError::Usage(format!(
+            "{} expects exactly one file",
+            command
+        ))),
+    };
+
+    let command = match command.as_str() {
+        "run" => {
+            run.file = single_file(positional)?;
+            Command::Run(run)
+        }
+        "resume" => {
+            run.file = single_file(positional)?;
+            run.resume = true;
+            Command::Run(run)
+        }
+        "debug" => {
+            run.file = single_file(positional)?;
+            Command::Debug(run)
+        }
+        "gdb" => {
+            run.file = single_file(positional)?;
+            Command::Gdb { run, listen }
+        }
+        "asm" => Command::Asm {
+            source: single_file(positional)?,
+            output: output.ok_or_else(|| CliError::Usage("asm requires -o <bin>".to_string()))?,
+            origin: run.load_addr,
+        },
+        "disasm" => Command::Disasm {
+            input: single_file(positional)?,
+            origin: run.load_addr,
+        },
+        "help" | "-h" | "--help" => Command::Help,
+        other => return Err(CliError::Usage(format!("unknown command {}", other))),
+    };
+
+    Ok(command)
+}
+
+// Returns the process exit code: the code passed to the exit syscall by the guest
+// program for `run` and 0 for other commands.
+pub fn execute(command: Command) -> Result<u8> {
+    match command {
+        Command::Run(options) => run(options),
+        Command::Debug(options) => debug(options),
+        Command::Gdb { run, listen } => serve_gdb(run, listen),
+        Command::Asm {
+            source,
+            output,
+            origin,
+        } => {
+            let bytes = assemble_file(&source, origin)?;
+            std::fs::write(&output, bytes).map_err(|err| CliError::Io(output, err))?;
+            Ok(0)
+        }
+        Command::Disasm { input, origin } => {
+            let bytes = std::fs::read(&input).map_err(|err| CliError::Io(input, err))?;
+            print!("{}", disasm::disassemble(&bytes, origin));
+            Ok(0)
+        }
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+    }
+}
+
+fn run(options: RunOptions) -> Result<u8> {
+    let mut cpu = setup(&options)?;
+    if let Some(format) = options.trace {
+        cpu.set_tracer(Some(tracer(format, options.trace_file.as_deref())?));
+    }
+
+    let result = match options.max_steps {
+        None => cpu.run().map_err(CliError::from),
+        Some(max_steps) => match cpu.run_steps(max_steps) {
+            Ok(_) if !cpu.is_halted() => Err(CliError::StepLimit(max_steps)),
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.into()),
+        },
+    };
+
+    // Dump the state even if the program failed, that's when it's needed the most
+    if options.dump_registers {
+        println!("{}", cpu.dump_registers());
+        println!("CYCLES  | {}\n", cpu.get_cycles());
+    }
+    for (start, length) in options.dump_memory.iter() {
+        println!("{}", cpu.dump_memory_hex(*start, *length));
+    }
+    for (start, length) in options.dump_code.iter() {
+        print!(
+            "{}",
+            disasm::disassemble_memory(cpu.get_bus(), *start, *length)
+        );
+    }
+    if let Some(path) = options.save_snapshot.as_ref() {
+        std::fs::write(path, cpu.save_snapshot()).map_err(|err| CliError::Io(path.clone(), err))?;
+    }
+
+    result.map(|_| guest_exit_code(&cpu))
+}
+
+// Commands are read from stdin, the exit code is the guest's one if it exited
+fn debug(options: RunOptions) -> Result<u8> {
+    let mut debugger = debugger(&options)?;
+    run_repl(&mut debugger, std::io::stdin().lock(), std::io::stdout())
+        .map_err(|err| CliError::Io(PathBuf::from("<stdio>"), err))?;
+
+    Ok(guest_exit_code(debugger.cpu()))
+}
+
+fn serve_gdb(options: RunOptions, listen: Listen) -> Result<u8> {
+    let mut debugger = debugger(&options)?;
+
+    match listen {
+        Listen::Tcp(port) => {
+            eprintln!("waiting for gdb on 127.0.0.1:{}", port);
+            gdb::listen_tcp(&mut debugger, port)
+                .map_err(|err| CliError::Io(PathBuf::from(format!("127.0.0.1:{}", port)), err))?;
+        }
+        #[cfg(unix)]
+        Listen::Unix(path) => {
+            eprintln!("waiting for gdb on {}", path.display());
+            gdb::listen_unix(&mut debugger, &path).map_err(|err| CliError::Io(path, err))?;
+        }
+        #[cfg(not(unix))]
+        Listen::Unix(_) => {
+            return Err(CliError::Usage(
+                "unix sockets are not supported on this platform".to_string(),
+            ))
+        }
+    }
+
+    Ok(guest_exit_code(debugger.cpu()))
+}
+
+// Codes that don't fit into the process exit code become 255 instead of wrapping around,
+// so that e.g. exit(256) doesn't report success
+fn guest_exit_code(cpu: &CPU) -> u8 {
+    cpu.exit_code()
+        .map_or(0, |code| u8::try_from(code).unwrap_or(u8::MAX))
+}
+
+fn debugger(options: &RunOptions) -> Result<Debugger> {
+    let mut debugger = Debugger::new(setup(options)?);
+    if let Some(limit) = options.history {
+        debugger.cpu_mut().set_history_limit(limit);
+    }
+
+    Ok(debugger)
+}
+
+// CPU with the devices from the options and the program loaded or the snapshot restored
+fn setup(options: &RunOptions) -> Result<CPU> {
+    let mut cpu = CPU::new();
+    for (addr, path) in options.roms.iter() {
+        let image = std::fs::read(path).map_err(|err| CliError::Io(path.clone(), err))?;
+        let end = u16::try_from(*addr as usize + image.len().max(1) - 1).map_err(|_| {
+            CliError::Usage(format!(
+                "ROM {} of {} bytes at 0x{:04X} doesn't fit into memory",
+                path.display(),
+                image.len(),
+                addr
+            ))
+        })?;
+        cpu.get_bus_mut()
+            .map(*addr..=end, Box::new(Rom::new(&image)));
+    }
+    if let Some(addr) = options.console {
+        cpu.get_bus_mut()
+            .map(addr..=addr.saturating_add(1), Box::new(Console::default()));
+    }
+    if let Some(addr) = options.timer {
+        cpu.get_bus_mut()
+            .map(addr..=addr.saturating_add(7), Box::new(Timer::new(0)));
+    }
+    if !options.cycle_costs.is_empty() {
+        let mut costs = CycleCosts::default();
+        for (mnemonic, cycles) in options.cycle_costs.iter() {
+            costs.set(mnemonic, *cycles);
+        }
+        cpu.set_cycle_costs(costs);
+    }
+    cpu.set_exception_mode(options.exceptions);
+    if options.resume {
+        let snapshot =
+            std::fs::read(&options.file).map_err(|err| CliError::Io(options.file.clone(), err))?;
+        cpu.restore_snapshot(&snapshot)
+            .map_err(|err| CliError::Snapshot(options.file.clone(), err))?;
+    } else {
+        let program = load_file(&options.file, options.load_addr)?;
+        cpu.load_program(program, options.load_addr)?;
+    }
+    if let Some(entry) = options.entry {
+        cpu.set_pc(entry);
+    }
+    // Given after a snapshot is restored, so they override the saved configuration
+    if !options.regions.is_empty() {
+        let protection = cpu.protection_mut();
+        for (index, region) in options.regions.iter().enumerate() {
+            protection.set_region(index, Some(*region));
+        }
+        protection.set_enabled(true);
+    }
+    if options.mpu_window.is_some() {
+        cpu.protection_mut().set_window(options.mpu_window);
+    }
+
+    Ok(cpu)
+}
+
+fn tracer(format: TraceFormat, path: Option<&Path>) -> Result<Box<dyn Tracer>> {
+    let output: Box<dyn std::io::Write> = match path {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .map_err(|err| CliError::Io(path.to_path_buf(), err))?;
+            Box::new(std::io::BufWriter::new(file))
+        }
+        None => Box::new(std::io::stdout()),
+    };
+
+    Ok(match format {
+        TraceFormat::Text => Box::new(TextTracer::new(output)),
+        TraceFormat::Json => Box::new(JsonTracer::new(output)),
+        TraceFormat::Binary => Box::new(BinaryTracer::new(output)),
+    })
+}
+
+// Sources are recognized by extension, anything else is a raw binary
+fn load_file(path: &Path, origin: u16) -> Result<Vec<u8>> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("s" | "asm") => assemble_file(path, origin),
+        _ => std::fs::read(path).map_err(|err| CliError::Io(path.to_path_buf(), err)),
+    }
+}
+
+fn assemble_file(path: &Path, origin: u16) -> Result<Vec<u8>> {
+    let source =
+        std::fs::read_to_string(path).map_err(|err| CliError::Io(path.to_path_buf(), err))?;
+    asm::assemble(&source, origin).map_err(|err| CliError::Asm(path.to_path_buf(), err))
+}
+
+fn parse_number(text: &str) -> Result<u64> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse::<u64>(),
+    };
+
+    parsed.map_err(|_| CliError::Usage(format!("invalid number {}", text)))
+}
+
+fn parse_u16(text: &str) -> Result<u16> {
+    u16::try_from(parse_number(text)?)
+        .map_err(|_| CliError::Usage(format!("{} does not fit into 16 bits", text)))
+}
+
+// <addr>:<len>, e.g. 0x100:32
+fn parse_range(text: &str) -> Result<(u16, u16)> {
+    let (start, length) = text
+        .split_once(':')
+        .ok_or_else(|| CliError::Usage(format!("expected <addr>:<len>, given {}", text)))?;
+
+    Ok((parse_u16(start)?, parse_u16(length)?))
+}
+
+// <addr>:<file>, e.g. 0xF000:bios.bin
+fn parse_rom(text: &str) -> Result<(u16, PathBuf)> {
+    let (addr, file) = text
+        .split_once(':')
+        .ok_or_else(|| CliError::Usage(format!("expected <addr>:<file>, given {}", text)))?;
+
+    Ok((parse_u16(addr)?, PathBuf::from(file)))
+}
+
+// <start>:<end>:<perms>, e.g. 0x100:0x1FF:rx
+fn parse_region(text: &str) -> Result<Region> {
+    let usage = || CliError::Usage(format!("expected <start>:<end>:<perms>, given {}", text));
+    let mut parts = text.split(':');
+    let (Some(start), Some(end), Some(perms), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(usage());
+    };
+
+    Ok(Region {
+        start: parse_u16(start)?,
+        end: parse_u16(end)?,
+        permissions: Permissions::parse(perms).ok_or_else(usage)?,
+    })
+}
+
+fn parse_exception_mode(text: &str) -> Result<ExceptionMode> {
+    match text {
+        "fatal" => Ok(ExceptionMode::Fatal),
+        "trap" => Ok(ExceptionMode::Trap),
+        _ => Err(CliError::Usage(format!("unknown exception mode {}", text))),
+    }
+}
+
+fn parse_trace_format(text: &str) -> Result<TraceFormat> {
+    match text {
+        "text" => Ok(TraceFormat::Text),
+        "json" => Ok(TraceFormat::Json),
+        "bin" => Ok(TraceFormat::Binary),
+        _ => Err(CliError::Usage(format!("unknown trace format {}", text))),
+    }
+}
+
+// <mnemonic>=<cycles>, e.g. LOAD=3
+fn parse_cost(text: &str) -> Result<(String, u32)> {
+    let (mnemonic, cycles) = text
+        .split_once('=')
+        .ok_or_else(|| CliError::Usage(format!("expected <op>=<n>, given {}", text)))?;
+    if !isa::is_mnemonic(mnemonic) {
+        return Err(CliError::Usage(format!("unknown instruction {}", mnemonic)));
+    }
+    let cycles = u32::try_from(parse_number(cycles)?)
+        .map_err(|_| CliError::Usage(format!("{} does not fit into 32 bits", cycles)))?;
+
+    Ok((mnemonic.to_string(), cycles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_run() {
+        let command = parse_args(&args(
+            "run prog.bin --load-addr 0x100 --entry 0x104 --max-steps 1000 --dump-regs --dump-mem 0x10:16 --dump-code 0x100:8 --console 0xFF00 --rom 0xF000:bios.bin --timer 0xFF10 --cycle-cost load=3 --trace json --trace-file out.jsonl --region 0x100:0x1FF:rx --mpu-window 0xFE00",
+        ))
+        .unwrap();
+
+        let Command::Run(options) = command else {
+            panic!("expected run command");
+        };
+        assert_eq!(options.file, PathBuf::from("prog.bin"));
+        assert_eq!(options.load_addr, 0x100);
+        assert_eq!(options.entry, Some(0x104));
+        assert_eq!(options.max_steps, Some(1000));
+        assert!(options.dump_registers);
+        assert_eq!(options.dump_memory, vec![(0x10, 16)]);
+        assert_eq!(options.dump_code, vec![(0x100, 8)]);
+        assert_eq!(options.console, Some(0xFF00));
+        assert_eq!(options.roms, vec![(0xF000, PathBuf::from("bios.bin"))]);
+        assert_eq!(options.timer, Some(0xFF10));
+        assert_eq!(options.cycle_costs, vec![("load".to_string(), 3)]);
+        assert_eq!(options.trace, Some(TraceFormat::Json));
+        assert_eq!(options.trace_file, Some(PathBuf::from("out.jsonl")));
+        assert_eq!(
+            options.regions,
+            vec![Region {
+                start: 0x100,
+                end: 0x1FF,
+                permissions: Permissions::READ | Permissions::EXECUTE,
+            }]
+        );
+        assert_eq!(options.mpu_window, Some(0xFE00));
+        assert!(!options.resume);
+
+        let Command::Run(options) = parse_args(&args(
+            "resume state.snap --save-snapshot next.snap --exceptions trap",
+        ))
+        .unwrap() else {
+            panic!("expected run command");
+        };
+        assert!(options.resume);
+        assert_eq!(options.file, PathBuf::from("state.snap"));
+        assert_eq!(options.save_snapshot, Some(PathBuf::from("next.snap")));
+        assert_eq!(options.exceptions, ExceptionMode::Trap);
+
+        let Command::Gdb { run, listen } =
+            parse_args(&args("gdb prog.s --socket /tmp/s16.sock --entry 0x10")).unwrap()
+        else {
+            panic!("expected gdb command");
+        };
+        assert_eq!(run.entry, Some(0x10));
+        assert_eq!(run.history, None);
+        assert_eq!(listen, Listen::Unix(PathBuf::from("/tmp/s16.sock")));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let usage = |line: &str| matches!(parse_args(&args(line)), Err(CliError::Usage(_)));
+
+        assert!(usage("asm prog.s"));
+        assert!(usage("run"));
+        assert!(usage("debug"));
+        assert!(usage("run a.bin b.bin"));
+        assert!(usage("run a.bin --load-addr 0x10000"));
+        assert!(usage("run a.bin --dump-mem 0x10"));
+        assert!(usage("run a.bin --entry"));
+        assert!(usage("run a.bin --cycle-cost LAOD=3"));
+        assert!(usage("run a.bin --cycle-cost JB=2"));
+        assert!(usage("run a.bin --trace xml"));
+        assert!(usage("run a.bin --exceptions ignore"));
+        assert!(usage("run a.bin --region 0x100:0x1FF"));
+        assert!(usage("run a.bin --region 0x100:0x1FF:rwz"));
+        assert!(usage("frobnicate"));
+    }
+
+    #[test]
+    fn test_program_too_large() {
+        let path = std::env::temp_dir().join(format!("s16vm-cli-{}.bin", std::process::id()));
+        // HALT at the last byte, then a full 64KB image
+        let cases: [(Vec<u8>, u16); 2] = [(vec![0x00, 0xFF], 0xFFFF), (vec![0; 0x10000], 0x0000)];
+
+        for (image, addr) in cases {
+            std::fs::write(&path, &image).unwrap();
+            let line = format!("run {} --load-addr 0x{:X}", path.display(), addr);
+            let Command::Run(options) = parse_args(&args(&line)).unwrap() else {
+                panic!("expected run command");
+            };
+            let result = setup(&options);
+            assert!(
+                matches!(
+                    result,
+                    Err(CliError::Cpu(CpuError::ProgramTooLarge { start, size }))
+                        if start == addr && size == image.len()
+                ),
+                "{}",
+                line
+            );
+        }
+
+        // a ROM running past the end of memory
+        std::fs::write(&path, [0; 2]).unwrap();
+        let line = format!("run {} --rom 0xFFFF:{}", path.display(), path.display());
+        let Command::Run(options) = parse_args(&args(&line)).unwrap() else {
+            panic!("expected run command");
+        };
+        assert!(matches!(setup(&options), Err(CliError::Usage(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_guest_exit_code() {
+        for (code, expected) in [(0x0000, 0), (0x00FF, 255), (0x0100, 255), (0xFFFF, 255)] {
+            let mut cpu = CPU::new();
+            cpu.load_program(asm::assemble("LOAD R2, 0x80\nSYSCALL", 0).unwrap(), 0)
+                .unwrap();
+            cpu.get_bus_mut().write_word(0x80, code).unwrap();
+            cpu.run().unwrap();
+            assert_eq!(guest_exit_code(&cpu), expected, "exit({})", code);
+        }
+    }
+}
@@ -1,85 +1,209 @@
 mod debug;
+mod history;
 mod implementations;
-mod memory;
-mod types;
+mod interrupts;
+pub(crate) mod types;
 
+pub mod bus;
+pub mod cycles;
+pub mod devices;
 pub mod error;
+pub mod exceptions;
 pub mod instructions;
+pub mod memory;
+pub mod privilege;
+pub mod protection;
+pub mod snapshot;
+pub mod syscall;
+pub mod trace;
+
+use std::collections::VecDeque;
 
 use error::CpuError;
-use instructions::{register::Register, word::Word, Instruction, Jump};
-use memory::Memory;
+use history::UndoEntry;
+use instructions::{register::Register, word::Word, Instruction};
+use bus::Bus;
+use cycles::CycleCosts;
+use exceptions::ExceptionMode;
+use protection::Protection;
+use syscall::{HostSyscalls, SyscallHandler};
+use trace::{RegisterChange, TraceRecord, Tracer, TRACED_REGISTERS};
 
 type Result<T> = std::result::Result<T, CpuError>;
 
-#[derive(Default)]
-struct Flags {
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flags {
     zero: bool,
     negative: bool,
     carry: bool,
     overflow: bool,
+
+    interrupt_enable: bool, // global interrupt enable
+    supervisor: bool,       // privilege mode, user mode when cleared
+    interrupt_mask: u8,     // one bit per interrupt line, 1 = line is masked
 }
 
 impl std::fmt::Display for Flags {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[Z:{} C:{} N:{} V:{}]", self.zero as u8, self.carry as u8, self.negative as u8, self.overflow as u8)
+        write!(f, "[Z:{} C:{} N:{} V:{} I:{} S:{} M:{:02X}]", self.zero as u8, self.carry as u8, self.negative as u8, self.overflow as u8, self.interrupt_enable as u8, self.supervisor as u8, self.interrupt_mask)
     }
 }
 
 impl Flags {
-    fn as_u16(&self) -> u16 {
-        (self.zero as u16) << 0
+    pub fn zero(&self) -> bool {
+        self.zero
+    }
+
+    pub fn negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn carry(&self) -> bool {
+        self.carry
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.overflow
+    }
+
+    pub fn interrupt_enable(&self) -> bool {
+        self.interrupt_enable
+    }
+
+    pub fn interrupt_mask(&self) -> u8 {
+        self.interrupt_mask
+    }
+
+    pub fn supervisor(&self) -> bool {
+        self.supervisor
+    }
+
+    // FLAGS register value as seen by MOVS: Z, C, N, V, I, S in bits 0-5, mask in bits 8-15
+    pub fn as_u16(&self) -> u16 {
+        (self.zero as u16)
             | (self.carry as u16) << 1
             | (self.negative as u16) << 2
             | (self.overflow as u16) << 3
+            | (self.interrupt_enable as u16) << 4
+            | (self.supervisor as u16) << 5
+            | (self.interrupt_mask as u16) << 8
     }
 
-    fn from_u16(&mut self, value: u16) {
+    pub fn set_u16(&mut self, value: u16) {
         self.zero = (value & 0x01) != 0;
         self.carry = (value & 0x02) != 0;
         self.negative = (value & 0x04) != 0;
         self.overflow = (value & 0x08) != 0;
+        self.interrupt_enable = (value & 0x10) != 0;
+        self.supervisor = (value & 0x20) != 0;
+        self.interrupt_mask = (value >> 8) as u8;
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     registers: [u16; 8], // 8 general-purpose registers R0-R7
     pc: u16,             // Program Counter
-    sp: u16,             // Stack Pointer
+    sp: u16,             // Stack Pointer of the current mode
+    other_sp: u16,       // Stack Pointer of the other mode, swapped in when the mode changes
     flags: Flags,        // CPU Flags (Z, C, N, V)
 
-    memory: Memory,
+    bus: Bus,
+    protection: Protection,
 
     halted: bool,
+    exit_code: Option<u16>,
+
+    pending_interrupts: u8, // one bit per interrupt line
+
+    cycles: u64, // cycles elapsed since the program was loaded
+    cycle_costs: CycleCosts,
+    exception_mode: ExceptionMode,
+
+    syscalls: Box<dyn SyscallHandler>,
+    tracer: Option<Box<dyn Tracer>>, // checked once per step, nothing else is done when unset
+
+    history: VecDeque<UndoEntry>, // most recent step last
+    history_limit: usize,         // 0 disables recording
 
     // used to control program bounderies
     program_start: u16,
     program_end: u16,
 }
 
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CPU {
     pub fn new() -> Self {
         Self {
             registers: [0; 8],
             pc: 0x0,
             sp: 0xFFFF,
-            flags: Flags::default(),
-            memory: Memory::default(),
+            other_sp: 0x0000,
+            // Starts in supervisor mode, the program drops to user mode if it wants to
+            flags: Flags {
+                supervisor: true,
+                ..Flags::default()
+            },
+            bus: Bus::default(),
+            protection: Protection::default(),
             halted: false,
+            exit_code: None,
+            pending_interrupts: 0,
+            cycles: 0,
+            cycle_costs: CycleCosts::default(),
+            exception_mode: ExceptionMode::default(),
+            syscalls: Box::new(HostSyscalls::default()),
+            tracer: None,
+            history: VecDeque::new(),
+            history_limit: 0,
             program_start: 0x0,
             program_end: 0x0,
         }
     }
     pub fn step(&mut self) -> Result<bool> {
+        if self.history_limit > 0 {
+            return self.step_recorded();
+        }
+
+        self.step_once()
+    }
+
+    fn step_once(&mut self) -> Result<bool> {
         if self.halted {
             return Ok(false);
         }
 
+        // Interrupts are taken between instructions
+        self.handle_interrupts()?;
+
+        // A faulting instruction leaves registers as they were before it
+        let (pc, sp, other_sp, registers, flags) =
+            (self.pc, self.sp, self.other_sp, self.registers, self.flags);
+        match self.execute_next() {
+            Err(err) if self.exception_mode == ExceptionMode::Trap => {
+                self.sp = sp;
+                self.other_sp = other_sp;
+                self.registers = registers;
+                self.flags = flags;
+                self.enter_exception(err, pc)?;
+            }
+            result => result?,
+        }
+
+        Ok(true)
+    }
+
+    fn execute_next(&mut self) -> Result<()> {
         // Security control
         self.secure_boundaries()?;
 
         // Fetch
-        let instruction_word = self.memory.read_word(self.pc)?;
+        let instruction_word = self.bus.fetch_word(self.pc)?;
         self.pc = self.pc.wrapping_add(2);
 
         // Decode
@@ -90,26 +214,83 @@ impl CPU {
         };
 
         // Exec
-        println!("{:04X}: {}", self.pc-2, instruction);
-        self.execute(instruction)?;
+        if self.tracer.is_none() {
+            self.execute(instruction)?;
+            self.spend_cycles(self.cycle_costs.cost(&instruction));
+        } else {
+            self.execute_traced(instruction, instruction_word)?;
+        }
 
-        Ok(true)
+        Ok(())
+    }
+
+    // Same as the untraced path of `step`, but reports what the instruction did to the tracer
+    fn execute_traced(&mut self, instruction: Instruction, raw: u16) -> Result<()> {
+        let pc = self.pc.wrapping_sub(2);
+        let before = TRACED_REGISTERS.map(|reg| self.get_register(reg));
+        let logging = self.bus.is_access_logging();
+        self.bus.set_access_logging(true);
+        let first_access = self.bus.accesses().len();
+
+        let result = self.execute(instruction);
+        let accesses = self.bus.accesses()[first_access..].to_vec();
+        self.bus.set_access_logging(logging);
+        result?;
+        self.spend_cycles(self.cycle_costs.cost(&instruction));
+
+        let changes = TRACED_REGISTERS
+            .iter()
+            .zip(before)
+            .filter_map(|(&register, old)| {
+                let new = self.get_register(register);
+                (old != new).then_some(RegisterChange { register, old, new })
+            })
+            .collect();
+        let record = TraceRecord {
+            pc,
+            raw,
+            instruction,
+            next_pc: self.pc,
+            cycles: self.cycles,
+            changes,
+            accesses,
+        };
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(&record).map_err(CpuError::HostIo)?;
+        }
+
+        Ok(())
     }
 
     // Load a program into memory with starting address start_addr.
     // Automatically sets program boundaries and pc.
+    // The program has to end below 0x10000, since its end is kept as a 16-bit address.
     pub fn load_program(&mut self, program: Vec<u8>, start_addr: u16) -> Result<()> {
-        let program_size = program.len() as u16;
+        let program_end = u16::try_from(program.len())
+            .ok()
+            .and_then(|size| start_addr.checked_add(size))
+            .ok_or(CpuError::ProgramTooLarge {
+                start: start_addr,
+                size: program.len(),
+            })?;
 
         self.program_start = start_addr;
-        self.program_end = start_addr + program_size;
+        self.program_end = program_end;
         self.halted = false;
+        self.exit_code = None;
+        self.pending_interrupts = 0;
+        self.cycles = 0;
+        self.history.clear();
         self.pc = start_addr;
         self.sp = 0xFFFE;
+        // Programs start in supervisor mode, without a user stack
+        self.flags.supervisor = true;
+        self.other_sp = 0x0000;
 
         for (i, &byte) in program.iter().enumerate() {
             let addr = start_addr + i as u16;
-            self.memory.write_byte(addr, byte)?;
+            self.bus.write_byte(addr, byte)?;
         }
 
         Ok(())
@@ -121,9 +302,30 @@ impl CPU {
         Ok(())
     }
 
+    // Run at most max_steps instructions, returns the number of executed instructions.
+    // The program is still running if it's equal to max_steps and CPU is not halted.
+    pub fn run_steps(&mut self, max_steps: u64) -> Result<u64> {
+        let mut steps = 0;
+        while steps < max_steps && self.step()? {
+            steps += 1;
+        }
+
+        Ok(steps)
+    }
+
+    // Advances time: devices see every cycle and may raise interrupts
+    fn spend_cycles(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+        self.pending_interrupts |= self.bus.tick(cycles);
+    }
+
     // Control program boundries, it's the simplest way to not fuck up.
-    // Later, it should be upgraded to hybrid system based on memory segments and CPU security polices.
+    // With the protection unit enabled its regions decide what can be executed instead.
     fn secure_boundaries(&self) -> Result<()> {
+        if self.protection.is_enabled() {
+            return self.check_fetch(self.pc);
+        }
+
         // Check if we can read full instruction and not became out of program boundaries.
         let instruction_end = self.pc.saturating_add(2);
         if self.pc < self.program_start || instruction_end > self.program_end {
@@ -138,7 +340,7 @@ impl CPU {
         }
     }
 
-    fn get_register(&self, reg: Register) -> u16 {
+    pub fn get_register(&self, reg: Register) -> u16 {
         use Register::*;
         match reg {
             R0 => 0,
@@ -146,18 +348,22 @@ impl CPU {
             SP => self.sp,
             PC => self.pc,
             FLAGS => self.flags.as_u16(),
+            USP if self.flags.supervisor => self.other_sp,
+            USP => self.sp,
         }
     }
 
-    fn set_register(&mut self, reg: Register, val: u16) {
+    pub fn set_register(&mut self, reg: Register, val: u16) {
         use Register::*;
 
         match reg {
-            R0 => return, // R0 can't be changed
+            R0 => {} // R0 can't be changed
             R1 | R2 | R3 | R4 | R5 | R6 | R7 => self.registers[reg as usize] = val,
             SP => self.sp = val,
             PC => self.pc = val,
-            FLAGS => self.flags.from_u16(val),
+            FLAGS => self.set_flags(val),
+            USP if self.flags.supervisor => self.other_sp = val,
+            USP => self.sp = val,
         }
     }
 
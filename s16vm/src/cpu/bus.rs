@@ -0,0 +1,384 @@
+use std::ops::RangeInclusive;
+
+use super::memory::{Memory, MemoryError, Result};
+
+// Anything that can be attached to the bus: RAM, ROM or a peripheral.
+// Addresses passed to a device are offsets from the start of its mapping.
+pub trait Device {
+    fn read_byte(&mut self, offset: u16) -> Result<u8>;
+    fn write_byte(&mut self, offset: u16, value: u8) -> Result<()>;
+
+    // Little-endian by default. Peripherals with side effects on read
+    // (e.g. input registers) should override word access.
+    fn read_word(&mut self, offset: u16) -> Result<u16> {
+        let low = self.read_byte(offset)? as u16;
+        let high = self.read_byte(offset.wrapping_add(1))? as u16;
+        Ok((high << 8) | low)
+    }
+
+    fn write_word(&mut self, offset: u16, value: u16) -> Result<()> {
+        self.write_byte(offset, (value & 0x00FF) as u8)?;
+        self.write_byte(offset.wrapping_add(1), (value >> 8) as u8)
+    }
+
+    // Read without side effects, used by dumps and debuggers.
+    // Devices that can't be inspected return None.
+    fn peek_byte(&self, _offset: u16) -> Option<u8> {
+        None
+    }
+
+    // Called once per executed instruction with the number of cycles it took.
+    // Returns an interrupt line (0-7) the device wants to raise.
+    fn tick(&mut self, _cycles: u32) -> Option<u8> {
+        None
+    }
+
+    // Internal state for snapshots. Devices whose state lives outside the machine
+    // (host streams, ROM images) return None and are left as they are on restore.
+    fn save_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    // Returns false if the state wasn't produced by the same kind of device
+    fn restore_state(&mut self, _state: &[u8]) -> bool {
+        false
+    }
+
+    // Plain RAM or ROM. Anything else is a peripheral, accessible only in supervisor mode.
+    fn is_memory(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+// A data access made through the bus, instruction fetches are not recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: u16,
+    pub size: u8, // 1 or 2 bytes
+    pub value: u16,
+}
+
+impl Access {
+    pub fn overlaps(&self, start: u16, end: u16) -> bool {
+        let last = self.address.saturating_add(self.size as u16 - 1);
+        self.address <= end && last >= start
+    }
+}
+
+struct Mapping {
+    range: RangeInclusive<u16>,
+    device: Box<dyn Device>,
+}
+
+// Routes CPU memory accesses to devices by address.
+// Mappings may overlap, the most recently mapped device wins,
+// so peripherals can be placed on top of the default RAM.
+pub struct Bus {
+    mappings: Vec<Mapping>,
+    accesses: Option<Vec<Access>>, // recorded only when logging is enabled
+    journal: Option<Vec<(u16, u8)>>, // bytes overwritten by data writes, for undo
+}
+
+impl Default for Bus {
+    // 64KB of RAM over the whole address space
+    fn default() -> Self {
+        let mut bus = Self::new();
+        bus.map(0x0000..=0xFFFF, Box::new(Memory::default()));
+        bus
+    }
+}
+
+impl Bus {
+    // Empty bus, every access fails with `MemoryError::Unmapped` until devices are mapped
+    pub fn new() -> Self {
+        Self {
+            mappings: Vec::new(),
+            accesses: None,
+            journal: None,
+        }
+    }
+
+    pub fn map(&mut self, range: RangeInclusive<u16>, device: Box<dyn Device>) {
+        self.mappings.push(Mapping { range, device });
+    }
+
+    pub fn read_byte(&mut self, address: u16) -> Result<u8> {
+        let value = self.load_byte(address)?;
+        self.record(AccessKind::Read, address, 1, value as u16);
+        Ok(value)
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) -> Result<()> {
+        self.journal_write(address, 1);
+        self.store_byte(address, value)?;
+        self.record(AccessKind::Write, address, 1, value as u16);
+        Ok(())
+    }
+
+    pub fn read_word(&mut self, address: u16) -> Result<u16> {
+        let value = self.load_word(address)?;
+        self.record(AccessKind::Read, address, 2, value);
+        Ok(value)
+    }
+
+    pub fn write_word(&mut self, address: u16, value: u16) -> Result<()> {
+        self.journal_write(address, 2);
+        self.store_word(address, value)?;
+        self.record(AccessKind::Write, address, 2, value);
+        Ok(())
+    }
+
+    // Instruction fetch, same as `read_word` but never recorded
+    pub fn fetch_word(&mut self, address: u16) -> Result<u16> {
+        self.load_word(address)
+    }
+
+    // Starts or stops recording of data accesses, stopping drops what was recorded
+    pub fn set_access_logging(&mut self, enabled: bool) {
+        match (enabled, self.accesses.is_some()) {
+            (true, false) => self.accesses = Some(Vec::new()),
+            (false, _) => self.accesses = None,
+            _ => {}
+        }
+    }
+
+    pub fn is_access_logging(&self) -> bool {
+        self.accesses.is_some()
+    }
+
+    // Accesses recorded so far, without consuming them
+    pub fn accesses(&self) -> &[Access] {
+        self.accesses.as_deref().unwrap_or_default()
+    }
+
+    // Accesses recorded since the last call
+    pub fn take_accesses(&mut self) -> Vec<Access> {
+        self.accesses.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // Starts or stops keeping the previous contents of written bytes
+    pub fn set_write_journal(&mut self, enabled: bool) {
+        self.journal = enabled.then(Vec::new);
+    }
+
+    // Overwritten bytes as (address, old value) in write order, since the last call
+    pub fn take_journal(&mut self) -> Vec<(u16, u8)> {
+        self.journal.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // Puts back bytes taken from the journal. Nothing is recorded, and bytes that
+    // can't be written back (e.g. ROM) are skipped.
+    pub fn undo_writes(&mut self, writes: &[(u16, u8)]) {
+        for &(address, old) in writes.iter().rev() {
+            let _ = self.store_byte(address, old);
+        }
+    }
+
+    // Address ranges of the mapped devices, in mapping order
+    pub fn layout(&self) -> Vec<RangeInclusive<u16>> {
+        self.mappings.iter().map(|m| m.range.clone()).collect()
+    }
+
+    // State of every mapped device, in mapping order
+    pub fn save_states(&self) -> Vec<Option<Vec<u8>>> {
+        self.mappings.iter().map(|m| m.device.save_state()).collect()
+    }
+
+    // Restores the device mapped `index`-th, false if there is no such device or it rejected the state
+    pub fn restore_state(&mut self, index: usize, state: &[u8]) -> bool {
+        self.mappings
+            .get_mut(index)
+            .is_some_and(|m| m.device.restore_state(state))
+    }
+
+    // True if a byte of the range is mapped to a peripheral rather than memory
+    pub fn is_mmio(&self, address: u16, size: u16) -> bool {
+        (0..size).any(|i| {
+            let Some(index) = address.checked_add(i).and_then(|a| self.find(a).ok()) else {
+                return false;
+            };
+            !self.mappings[index].device.is_memory()
+        })
+    }
+
+    pub fn peek_byte(&self, address: u16) -> Option<u8> {
+        let mapping = &self.mappings[self.find(address).ok()?];
+        mapping.device.peek_byte(address - mapping.range.start())
+    }
+
+    // Side effect free copy of a memory range, bytes which can't be peeked read as 0.
+    // Empty if the range goes beyond the address space.
+    pub fn get_range(&self, start: u16, length: u16) -> Vec<u8> {
+        if start as u32 + length as u32 > 0x10000 {
+            return Vec::new();
+        }
+
+        (0..length)
+            .map(|i| self.peek_byte(start + i).unwrap_or(0))
+            .collect()
+    }
+
+    // Ticks every device, returns a bit mask of raised interrupt lines
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        let mut raised = 0;
+        for mapping in self.mappings.iter_mut() {
+            if let Some(line) = mapping.device.tick(cycles) {
+                raised |= 1 << (line & 0x7);
+            }
+        }
+        raised
+    }
+
+    // Only bytes that can be peeked are kept, devices with side effects can't be undone anyway
+    fn journal_write(&mut self, address: u16, size: u16) {
+        if self.journal.is_none() {
+            return;
+        }
+        let old: Vec<(u16, u8)> = (0..size)
+            .filter_map(|i| {
+                let address = address.checked_add(i)?;
+                Some((address, self.peek_byte(address)?))
+            })
+            .collect();
+        if let Some(journal) = self.journal.as_mut() {
+            journal.extend(old);
+        }
+    }
+
+    fn record(&mut self, kind: AccessKind, address: u16, size: u8, value: u16) {
+        if let Some(accesses) = self.accesses.as_mut() {
+            accesses.push(Access {
+                kind,
+                address,
+                size,
+                value,
+            });
+        }
+    }
+
+    fn load_byte(&mut self, address: u16) -> Result<u8> {
+        let (start, device) = self.device_mut(address)?;
+        device
+            .read_byte(address - start)
+            .map_err(|err| absolute(err, start))
+    }
+
+    fn store_byte(&mut self, address: u16, value: u8) -> Result<()> {
+        let (start, device) = self.device_mut(address)?;
+        device
+            .write_byte(address - start, value)
+            .map_err(|err| absolute(err, start))
+    }
+
+    fn load_word(&mut self, address: u16) -> Result<u16> {
+        if address == 0xFFFF {
+            return Err(MemoryError::OutOfBounds(address));
+        }
+
+        // A word crossing a mapping boundary is split into two byte accesses
+        if self.find(address)? != self.find(address + 1)? {
+            let low = self.load_byte(address)? as u16;
+            let high = self.load_byte(address + 1)? as u16;
+            return Ok((high << 8) | low);
+        }
+
+        let (start, device) = self.device_mut(address)?;
+        device
+            .read_word(address - start)
+            .map_err(|err| absolute(err, start))
+    }
+
+    fn store_word(&mut self, address: u16, value: u16) -> Result<()> {
+        if address == 0xFFFF {
+            return Err(MemoryError::OutOfBounds(address));
+        }
+
+        if self.find(address)? != self.find(address + 1)? {
+            self.store_byte(address, (value & 0x00FF) as u8)?;
+            return self.store_byte(address + 1, (value >> 8) as u8);
+        }
+
+        let (start, device) = self.device_mut(address)?;
+        device
+            .write_word(address - start, value)
+            .map_err(|err| absolute(err, start))
+    }
+
+    fn find(&self, address: u16) -> Result<usize> {
+        self.mappings
+            .iter()
+            .rposition(|m| m.range.contains(&address))
+            .ok_or(MemoryError::Unmapped(address))
+    }
+
+    fn device_mut(&mut self, address: u16) -> Result<(u16, &mut dyn Device)> {
+        let idx = self.find(address)?;
+        let mapping = &mut self.mappings[idx];
+        Ok((*mapping.range.start(), mapping.device.as_mut()))
+    }
+}
+
+// Devices report offsets, the rest of the system wants addresses
+fn absolute(err: MemoryError, start: u16) -> MemoryError {
+    match err {
+        MemoryError::OutOfBounds(offset) => MemoryError::OutOfBounds(offset.wrapping_add(start)),
+        MemoryError::Unmapped(offset) => MemoryError::Unmapped(offset.wrapping_add(start)),
+        MemoryError::ReadOnly(offset) => MemoryError::ReadOnly(offset.wrapping_add(start)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::memory::Rom;
+
+    #[test]
+    fn test_routing() {
+        let mut bus = Bus::new();
+        bus.map(0x0000..=0x00FF, Box::new(Rom::new(&[0xCD, 0xAB, 0x42])));
+        bus.map(0x1000..=0x10FF, Box::new(Memory::new(0x100)));
+
+        assert_eq!(bus.read_word(0x0000).unwrap(), 0xABCD);
+        assert_eq!(bus.read_byte(0x0002).unwrap(), 0x42);
+        assert!(matches!(
+            bus.write_byte(0x0001, 0),
+            Err(MemoryError::ReadOnly(0x0001))
+        ));
+        assert!(matches!(
+            bus.read_byte(0x0003),
+            Err(MemoryError::OutOfBounds(0x0003))
+        ));
+        assert!(matches!(
+            bus.read_byte(0x0800),
+            Err(MemoryError::Unmapped(0x0800))
+        ));
+
+        bus.write_word(0x1010, 0xBEEF).unwrap();
+        assert_eq!(bus.read_byte(0x1010).unwrap(), 0xEF);
+        assert_eq!(bus.get_range(0x100F, 3), vec![0x00, 0xEF, 0xBE]);
+    }
+
+    #[test]
+    fn test_overlay_and_crossing() {
+        let mut bus = Bus::default();
+        bus.map(0x8000..=0x8001, Box::new(Rom::new(&[0x11, 0x22])));
+
+        bus.write_byte(0x7FFF, 0x33).unwrap();
+        assert_eq!(bus.read_word(0x7FFF).unwrap(), 0x1133); // crosses RAM -> ROM
+        assert_eq!(bus.read_word(0x8000).unwrap(), 0x2211);
+        assert!(bus.write_word(0x8001, 0xFFFF).is_err());
+
+        assert!(matches!(
+            bus.read_word(0xFFFF),
+            Err(MemoryError::OutOfBounds(0xFFFF))
+        ));
+        assert_eq!(bus.get_range(0xFFFF, 1), vec![0x00]);
+    }
+}
@@ -0,0 +1,91 @@
+use super::instructions::{isa, Instruction};
+
+// Number of cycles each instruction takes, kept per instruction of the ISA table.
+// Defaults: 1 cycle for ALU and control instructions, 2 for memory and stack access and
+// multiplication, 3 for CALL, CALLR, RET and RETI which touch both the stack and PC, 4 for division.
+pub struct CycleCosts {
+    costs: Vec<u32>,
+}
+
+// Cycles spent on entering an interrupt handler (two pushes and a vector fetch)
+pub const INTERRUPT_ENTRY_CYCLES: u32 = 3;
+
+// Same for an exception handler
+pub const EXCEPTION_ENTRY_CYCLES: u32 = 3;
+
+impl Default for CycleCosts {
+    fn default() -> Self {
+        let mut costs = Self::uniform(1);
+        for mnemonic in [
+            "LOAD", "STORE", "LOADO", "STOREO", "LOADI", "STOREI", "LOADB", "LOADBU", "STOREB",
+            "PUSH", "POP", "MUL", "MULH", "MULHU",
+        ] {
+            costs.set(mnemonic, 2);
+        }
+        for mnemonic in ["CALL", "CALLR", "RET", "RETI"] {
+            costs.set(mnemonic, 3);
+        }
+        for mnemonic in ["DIV", "DIVU", "REM", "REMU"] {
+            costs.set(mnemonic, 4);
+        }
+        costs
+    }
+}
+
+impl CycleCosts {
+    // Every instruction takes the same number of cycles
+    pub fn uniform(cycles: u32) -> Self {
+        Self {
+            costs: vec![cycles; isa::ENTRY_COUNT],
+        }
+    }
+
+    // Overrides the cost of every instruction written with `mnemonic`, e.g. both forms of MOVS.
+    // Returns false if there is none, see `isa::is_mnemonic`.
+    pub fn set(&mut self, mnemonic: &str, cycles: u32) -> bool {
+        let mut found = false;
+        for entry in isa::entries(mnemonic) {
+            self.costs[entry] = cycles;
+            found = true;
+        }
+        found
+    }
+
+    // Overrides the cost of the kind of `instruction`, its operands don't matter
+    pub fn set_instruction(&mut self, instruction: &Instruction, cycles: u32) {
+        self.costs[instruction.entry()] = cycles;
+    }
+
+    pub fn cost(&self, instruction: &Instruction) -> u32 {
+        self.costs[instruction.entry()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::instructions::{register::Register, Jump};
+
+    #[test]
+    fn test_costs() {
+        let mut costs = CycleCosts::default();
+        let jump = |jump_type| Instruction::Jump {
+            jump_type,
+            offset: 0,
+        };
+        assert_eq!(costs.cost(&Instruction::Nop), 1);
+        assert_eq!(costs.cost(&jump(Jump::Call)), 3);
+
+        // both forms of MOVS, only the given kind of jump
+        assert!(costs.set("movs", 5));
+        costs.set_instruction(&jump(Jump::Carry), 7);
+        let (rt, spec) = (Register::R1, Register::SP);
+        assert_eq!(costs.cost(&Instruction::MoveFromSpecial { rt, spec }), 5);
+        assert_eq!(costs.cost(&Instruction::MoveFromToSpecial { rt, spec }), 5);
+        assert_eq!(costs.cost(&jump(Jump::Carry)), 7);
+        assert_eq!(costs.cost(&jump(Jump::NotCarry)), 1);
+
+        assert!(!costs.set("LAOD", 3));
+        assert!(!costs.set("JB", 3));
+    }
+}
@@ -1,4 +1,4 @@
-use super::{instructions::register::Register, memory::Memory, Flags, CPU};
+use super::{bus::Bus, cycles::CycleCosts, instructions::register::Register, syscall::SyscallHandler, trace::Tracer, Flags, CPU};
 
 impl CPU {
     pub fn get_registers(&self) -> &[u16] {
@@ -17,8 +17,44 @@ impl CPU {
         &self.flags
     }
 
-    pub fn get_memory(&self) -> &Memory {
-        &self.memory
+    pub fn get_bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    // Used to map devices before running a program
+    pub fn get_bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // Code passed to the exit syscall, None if the program stopped any other way
+    pub fn exit_code(&self) -> Option<u16> {
+        self.exit_code
+    }
+
+    // Overrides the entry point set by `load_program`
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_cycle_costs(&mut self, costs: CycleCosts) {
+        self.cycle_costs = costs;
+    }
+
+    pub fn set_syscall_handler(&mut self, handler: Box<dyn SyscallHandler>) {
+        self.syscalls = handler;
+    }
+
+    // Every executed instruction is reported to the tracer, None turns tracing off
+    pub fn set_tracer(&mut self, tracer: Option<Box<dyn Tracer>>) {
+        self.tracer = tracer;
     }
 
     pub fn dump_registers(&self) -> String {
@@ -35,10 +71,11 @@ impl CPU {
             Register::PC,
             Register::SP,
             Register::FLAGS,
+            Register::USP,
         ];
 
-        output.push_str(&format!("REG     | HEX    | BIN                | DEC\n"));
-        output.push_str(&format!("--------|--------|--------------------|----\n"));
+        output.push_str("REG     | HEX    | BIN                | DEC\n");
+        output.push_str("--------|--------|--------------------|----\n");
         for reg in registers.iter() {
             match reg {
                 Register::FLAGS => {
@@ -57,7 +94,7 @@ impl CPU {
 
     pub fn dump_memory_hex(&self, start: u16, length: u16) -> String {
         let mut output = String::new();
-        let data = self.memory.get_range(start, length);
+        let data = self.bus.get_range(start, length);
 
         for (i, byte) in data.iter().enumerate() {
             if i % 16 == 0 {
@@ -0,0 +1,77 @@
+use std::io::{Read, Write};
+
+use crate::cpu::{
+    bus::Device,
+    memory::{MemoryError, Result},
+};
+
+pub const DATA: u16 = 0x0; // write: output a byte, read: next input byte (0 on EOF)
+pub const STATUS: u16 = 0x1; // bit 0: input reached EOF
+
+// Memory-mapped character console occupying 2 bytes
+pub struct Console {
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+    eof: bool,
+}
+
+impl Console {
+    pub fn new(input: Box<dyn Read>, output: Box<dyn Write>) -> Self {
+        Self {
+            input,
+            output,
+            eof: false,
+        }
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new(Box::new(std::io::stdin()), Box::new(std::io::stdout()))
+    }
+}
+
+impl Device for Console {
+    fn read_byte(&mut self, offset: u16) -> Result<u8> {
+        match offset {
+            DATA => {
+                let mut byte = [0u8; 1];
+                match self.input.read(&mut byte) {
+                    Ok(1) => Ok(byte[0]),
+                    _ => {
+                        self.eof = true;
+                        Ok(0)
+                    }
+                }
+            }
+            STATUS => Ok(self.eof as u8),
+            _ => Err(MemoryError::OutOfBounds(offset)),
+        }
+    }
+
+    fn write_byte(&mut self, offset: u16, value: u8) -> Result<()> {
+        match offset {
+            DATA => {
+                // The guest has no way to handle host errors, output is best effort
+                let _ = self.output.write_all(&[value]);
+                let _ = self.output.flush();
+                Ok(())
+            }
+            STATUS => Ok(()),
+            _ => Err(MemoryError::OutOfBounds(offset)),
+        }
+    }
+
+    // Registers are byte-wide, word access touches only the addressed one
+    fn read_word(&mut self, offset: u16) -> Result<u16> {
+        self.read_byte(offset).map(|b| b as u16)
+    }
+
+    fn write_word(&mut self, offset: u16, value: u16) -> Result<()> {
+        self.write_byte(offset, value as u8)
+    }
+
+    fn peek_byte(&self, offset: u16) -> Option<u8> {
+        (offset == STATUS).then_some(self.eof as u8)
+    }
+}
@@ -0,0 +1,230 @@
+use crate::cpu::{
+    bus::Device,
+    memory::{MemoryError, Result},
+};
+
+// Register layout, 8 bytes. Word registers are little-endian.
+pub const COUNTER: u16 = 0x0; // word: current value, counts down to 0
+pub const RELOAD: u16 = 0x2; // word: value loaded into COUNTER on start and in periodic mode
+pub const PRESCALER: u16 = 0x4; // word: COUNTER decrements every PRESCALER + 1 cycles
+pub const CONTROL: u16 = 0x6; // byte: see CTRL_* bits
+pub const STATUS: u16 = 0x7; // byte: bit 0 set when COUNTER reached 0, any write clears it
+
+pub const CTRL_ENABLE: u8 = 0x01;
+pub const CTRL_PERIODIC: u8 = 0x02; // reload and keep counting, otherwise stop (one-shot)
+pub const CTRL_INTERRUPT: u8 = 0x04; // raise the interrupt line on expiration
+
+// Programmable down-counting timer driven by CPU cycles.
+// Setting CTRL_ENABLE loads COUNTER from RELOAD.
+pub struct Timer {
+    line: u8,
+    counter: u16,
+    reload: u16,
+    prescaler: u16,
+    control: u8,
+    expired: bool,
+    cycles: u32, // cycles accumulated towards the next COUNTER decrement
+}
+
+impl Timer {
+    // Timer raising interrupt `line` when enabled by CTRL_INTERRUPT
+    pub fn new(line: u8) -> Self {
+        Self {
+            line,
+            counter: 0,
+            reload: 0,
+            prescaler: 0,
+            control: 0,
+            expired: false,
+            cycles: 0,
+        }
+    }
+
+    fn register(&self, offset: u16) -> Result<u8> {
+        let byte = |word: u16| (word >> (8 * (offset & 1))) as u8;
+        match offset {
+            CONTROL => Ok(self.control),
+            STATUS => Ok(self.expired as u8),
+            _ => match offset & !1 {
+                COUNTER => Ok(byte(self.counter)),
+                RELOAD => Ok(byte(self.reload)),
+                PRESCALER => Ok(byte(self.prescaler)),
+                _ => Err(MemoryError::OutOfBounds(offset)),
+            },
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.control & CTRL_ENABLE != 0
+    }
+
+    // Returns true when the counter reaches zero
+    fn count(&mut self) -> bool {
+        self.counter = self.counter.saturating_sub(1);
+        if self.counter != 0 {
+            return false;
+        }
+
+        self.expired = true;
+        if self.control & CTRL_PERIODIC != 0 && self.reload != 0 {
+            self.counter = self.reload;
+        } else {
+            self.control &= !CTRL_ENABLE;
+        }
+        true
+    }
+}
+
+impl Device for Timer {
+    fn read_byte(&mut self, offset: u16) -> Result<u8> {
+        self.register(offset)
+    }
+
+    fn write_byte(&mut self, offset: u16, value: u8) -> Result<()> {
+        let set_byte = |word: &mut u16| {
+            let shift = 8 * (offset & 1);
+            *word = (*word & !(0xFF << shift)) | (value as u16) << shift;
+        };
+
+        match offset {
+            CONTROL => {
+                let starting = !self.enabled() && value & CTRL_ENABLE != 0;
+                self.control = value;
+                if starting {
+                    self.counter = self.reload;
+                    self.cycles = 0;
+                }
+            }
+            STATUS => self.expired = false,
+            _ => match offset & !1 {
+                COUNTER => set_byte(&mut self.counter),
+                RELOAD => set_byte(&mut self.reload),
+                PRESCALER => set_byte(&mut self.prescaler),
+                _ => return Err(MemoryError::OutOfBounds(offset)),
+            },
+        }
+
+        Ok(())
+    }
+
+    fn peek_byte(&self, offset: u16) -> Option<u8> {
+        self.register(offset).ok()
+    }
+
+    fn tick(&mut self, cycles: u32) -> Option<u8> {
+        if !self.enabled() {
+            return None;
+        }
+
+        let mut fired = false;
+        self.cycles += cycles;
+        while self.enabled() && self.cycles > self.prescaler as u32 {
+            self.cycles -= self.prescaler as u32 + 1;
+            fired |= self.count();
+        }
+
+        (fired && self.control & CTRL_INTERRUPT != 0).then_some(self.line)
+    }
+
+    // counter, reload, prescaler, control, expired, cycles; the line is configuration
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let mut state = Vec::with_capacity(12);
+        state.extend_from_slice(&self.counter.to_le_bytes());
+        state.extend_from_slice(&self.reload.to_le_bytes());
+        state.extend_from_slice(&self.prescaler.to_le_bytes());
+        state.push(self.control);
+        state.push(self.expired as u8);
+        state.extend_from_slice(&self.cycles.to_le_bytes());
+        Some(state)
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> bool {
+        let [c0, c1, r0, r1, p0, p1, control, expired, y0, y1, y2, y3] = *state else {
+            return false;
+        };
+        self.counter = u16::from_le_bytes([c0, c1]);
+        self.reload = u16::from_le_bytes([r0, r1]);
+        self.prescaler = u16::from_le_bytes([p0, p1]);
+        self.control = control;
+        self.expired = expired != 0;
+        self.cycles = u32::from_le_bytes([y0, y1, y2, y3]);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asm::assemble,
+        cpu::{cycles::CycleCosts, CPU},
+    };
+
+    #[test]
+    fn test_one_shot_and_periodic() {
+        let mut timer = Timer::new(3);
+        timer.write_word(RELOAD, 3).unwrap();
+        timer.write_word(PRESCALER, 1).unwrap(); // every 2 cycles
+        timer
+            .write_byte(CONTROL, CTRL_ENABLE | CTRL_INTERRUPT)
+            .unwrap();
+
+        assert_eq!(timer.tick(5), None);
+        assert_eq!(timer.read_word(COUNTER).unwrap(), 1);
+        assert_eq!(timer.tick(1), Some(3));
+        assert_eq!(timer.read_byte(STATUS).unwrap(), 1);
+        assert_eq!(timer.read_byte(CONTROL).unwrap() & CTRL_ENABLE, 0);
+        assert_eq!(timer.tick(100), None);
+
+        timer.write_byte(STATUS, 0).unwrap();
+        timer
+            .write_byte(CONTROL, CTRL_ENABLE | CTRL_PERIODIC)
+            .unwrap();
+        assert_eq!(timer.tick(6), None); // interrupt is not enabled
+        assert_eq!(timer.read_byte(STATUS).unwrap(), 1);
+        assert_eq!(timer.read_word(COUNTER).unwrap(), 3);
+        assert!(timer.enabled());
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        // Periodic timer at 0xFF10 on line 1 counting every 10 cycles,
+        // the handler increments R3 until it reaches 3
+        let src = "
+            .word 0, handler, 0, 0, 0, 0, 0, 0
+        start:
+            LUI  R1, 0xFF
+            ORI  R1, 0x10
+            ADDI R2, 10
+            STOREI R2, R1           # COUNTER
+            ADDI R1, 2
+            STOREI R2, R1           # RELOAD = 10
+            ADDI R1, 4
+            ADD  R2, R0, R0
+            ADDI R2, 7
+            STOREI R2, R1           # CONTROL = ENABLE | PERIODIC | INTERRUPT
+            MOVS R1, FLAGS
+            ORI  R1, 0x10
+            MOVS FLAGS, R1
+        loop:
+            CMPI R3, 3
+            JNZ  loop
+            HALT
+        handler:
+            ADDI R3, 1
+            RETI
+        ";
+
+        let mut cpu = CPU::new();
+        cpu.set_cycle_costs(CycleCosts::uniform(1));
+        cpu.get_bus_mut()
+            .map(0xFF10..=0xFF17, Box::new(Timer::new(1)));
+        cpu.load_program(assemble(src, 0).unwrap(), 0).unwrap();
+        cpu.set_pc(0x10);
+
+        cpu.run_steps(1000).unwrap();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.get_registers()[3], 3);
+        assert!(cpu.get_cycles() >= 30);
+    }
+}
@@ -1,4 +1,4 @@
-use super::{instructions::{self, error::InstructionError, register::{Register}}, memory::MemoryError};
+use super::{instructions::{self, error::InstructionError}, memory::MemoryError};
 use instructions::word::Word;
 
 #[derive(Debug)]
@@ -6,19 +6,33 @@ pub enum CpuError {
     InvalidInstruction(Word, InstructionError),
     MemoryOutOfBounds(MemoryError),
     ProgramBoundsViolation{pc:u16, iend: u16, low: u16, high: u16},
+    ProgramTooLarge { start: u16, size: usize },
     StackOverflow,
-    NotImplementedYet,
+    ExecuteViolation(u16),
+    WriteViolation(u16),
+    ReadViolation(u16),
+    PrivilegeViolation(u16),
+    DivideByZero(u16),
+    InvalidSyscall(u16),
+    HostIo(std::io::Error),
 }
 
 impl std::fmt::Display for CpuError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             CpuError::InvalidInstruction(w, err) => write!(f, "invalid instruction 0x{:X}: {}", w, err),
-            CpuError::MemoryOutOfBounds(MemoryError::OutOfBounds(addr)) => write!(f, "memory out of bounds addr=0x{:X}", addr),
+            CpuError::MemoryOutOfBounds(err) => write!(f, "{}", err),
             CpuError::StackOverflow => write!(f, "stack overflow"),
-            CpuError::NotImplementedYet => write!(f, "instruction is not implemented yet"),
+            CpuError::ExecuteViolation(addr) => write!(f, "execute from non-executable memory at 0x{:04X}", addr),
+            CpuError::WriteViolation(addr) => write!(f, "write to protected memory at 0x{:04X}", addr),
+            CpuError::ReadViolation(addr) => write!(f, "read from unmapped or protected memory at 0x{:04X}", addr),
+            CpuError::PrivilegeViolation(pc) => write!(f, "privileged operation in user mode at 0x{:04X}", pc),
+            CpuError::DivideByZero(pc) => write!(f, "division by zero at 0x{:04X}", pc),
+            CpuError::InvalidSyscall(number) => write!(f, "invalid syscall 0x{:X}", number),
+            CpuError::HostIo(err) => write!(f, "host i/o error: {}", err),
             CpuError::ProgramBoundsViolation { pc, iend, low, high } => 
                 write!(f, "PC violation: 0x{:04X} (instruction ends at {:04X}) outside program boundaries [{:04X}, {:04X}]", pc, iend, low, high),
+            CpuError::ProgramTooLarge { start, size } => write!(f, "program of {} bytes at 0x{:04X} doesn't fit into memory", size, start),
         }
     }
 }
@@ -26,7 +40,9 @@ impl std::fmt::Display for CpuError {
 impl From<MemoryError> for CpuError {
     fn from(err: MemoryError) -> Self {
         match err {
-            MemoryError::OutOfBounds(_) => Self::MemoryOutOfBounds(err)
+            MemoryError::OutOfBounds(_) | MemoryError::Unmapped(_) | MemoryError::ReadOnly(_) => {
+                Self::MemoryOutOfBounds(err)
+            }
         }
     }
 }
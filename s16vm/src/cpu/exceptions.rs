@@ -0,0 +1,148 @@
+use super::{cycles::EXCEPTION_ENTRY_CYCLES, error::CpuError, Result, CPU};
+
+// What happens when an instruction faults
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionMode {
+    // The error is returned to the host and the program stops
+    #[default]
+    Fatal,
+    // The program handles the fault: the handler is entered in supervisor mode with the PC
+    // of the faulting instruction, FLAGS and the cause code pushed to the supervisor stack.
+    // The instruction has no effect on registers, a store may already have reached memory
+    // if the fault happened on a later access. Faults while entering the handler
+    // (e.g. no stack left) and host I/O errors are fatal.
+    Trap,
+}
+
+// Handler address is the word at EXCEPTION_VECTOR, right after the interrupt vector table
+pub const EXCEPTION_VECTOR: u16 = 0x0010;
+
+// Cause codes pushed on exception entry
+pub const CAUSE_INVALID_INSTRUCTION: u16 = 0x1;
+pub const CAUSE_MEMORY: u16 = 0x2;
+pub const CAUSE_PROGRAM_BOUNDS: u16 = 0x3;
+pub const CAUSE_STACK_OVERFLOW: u16 = 0x4;
+pub const CAUSE_EXECUTE_VIOLATION: u16 = 0x5;
+pub const CAUSE_WRITE_VIOLATION: u16 = 0x6;
+pub const CAUSE_READ_VIOLATION: u16 = 0x7;
+pub const CAUSE_INVALID_SYSCALL: u16 = 0x8;
+pub const CAUSE_PRIVILEGE_VIOLATION: u16 = 0x9;
+pub const CAUSE_DIVIDE_BY_ZERO: u16 = 0xA;
+
+impl CpuError {
+    // Cause code of the exception raised for the error, None if it can't be handled by the program
+    pub fn cause(&self) -> Option<u16> {
+        match self {
+            CpuError::InvalidInstruction(..) => Some(CAUSE_INVALID_INSTRUCTION),
+            CpuError::MemoryOutOfBounds(_) => Some(CAUSE_MEMORY),
+            CpuError::ProgramBoundsViolation { .. } => Some(CAUSE_PROGRAM_BOUNDS),
+            CpuError::StackOverflow => Some(CAUSE_STACK_OVERFLOW),
+            CpuError::ExecuteViolation(_) => Some(CAUSE_EXECUTE_VIOLATION),
+            CpuError::WriteViolation(_) => Some(CAUSE_WRITE_VIOLATION),
+            CpuError::ReadViolation(_) => Some(CAUSE_READ_VIOLATION),
+            CpuError::InvalidSyscall(_) => Some(CAUSE_INVALID_SYSCALL),
+            CpuError::PrivilegeViolation(_) => Some(CAUSE_PRIVILEGE_VIOLATION),
+            CpuError::DivideByZero(_) => Some(CAUSE_DIVIDE_BY_ZERO),
+            CpuError::HostIo(_) | CpuError::ProgramTooLarge { .. } => None,
+        }
+    }
+}
+
+impl CPU {
+    pub fn exception_mode(&self) -> ExceptionMode {
+        self.exception_mode
+    }
+
+    pub fn set_exception_mode(&mut self, mode: ExceptionMode) {
+        self.exception_mode = mode;
+    }
+
+    // Enters the handler like an interrupt does, with the cause pushed on top of the frame.
+    // After popping the cause RETI resumes the faulting instruction. Returns the original
+    // error if it can't be handled, PC is left at the faulting instruction then.
+    pub(super) fn enter_exception(&mut self, err: CpuError, pc: u16) -> Result<()> {
+        self.pc = pc;
+        let Some(cause) = err.cause() else {
+            return Err(err);
+        };
+
+        match self.enter_trap(EXCEPTION_VECTOR, Some(cause)) {
+            Ok(()) => {
+                self.spend_cycles(EXCEPTION_ENTRY_CYCLES);
+                Ok(())
+            }
+            Err(_) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, cpu::instructions::register::Register};
+
+    // Interrupt vectors, the exception and syscall vectors and the code from 0x0014.
+    // The handler skips faulting instructions and counts them in R3.
+    const PROGRAM: &str = "
+        .word 0, 0, 0, 0, 0, 0, 0, 0, handler, 0
+    start:
+        .word 0xFD00        # invalid E-type subcode
+        ADDI R4, 1
+        LUI  R5, 0xFF
+        ORI  R5, 0xFF
+        LOADI R6, R5        # word at 0xFFFF
+        HALT
+
+    handler:
+        POP  R1             # cause
+        POP  R5             # FLAGS
+        POP  R2             # faulting PC
+        ADDI R2, 2
+        PUSH R2
+        PUSH R5
+        ADDI R3, 1
+        RETI
+    ";
+
+    fn machine(mode: ExceptionMode) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(PROGRAM, 0).unwrap(), 0).unwrap();
+        cpu.set_pc(0x14);
+        cpu.set_exception_mode(mode);
+        cpu
+    }
+
+    #[test]
+    fn test_trap() {
+        let mut cpu = machine(ExceptionMode::Trap);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_sp(), 0xFFF8);
+        assert_eq!(
+            cpu.get_bus().get_range(0xFFF8, 6),
+            vec![0x01, 0x00, 0x20, 0x00, 0x14, 0x00]
+        );
+
+        cpu.run().unwrap();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.get_registers()[1], CAUSE_MEMORY);
+        assert_eq!(cpu.get_registers()[2], 0x1E);
+        assert_eq!(cpu.get_registers()[3], 2);
+        assert_eq!(cpu.get_registers()[4], 1);
+        assert_eq!(cpu.get_sp(), 0xFFFE);
+    }
+
+    #[test]
+    fn test_fatal() {
+        let mut cpu = machine(ExceptionMode::default());
+        assert!(matches!(cpu.step(), Err(CpuError::InvalidInstruction(..))));
+        assert_eq!(cpu.get_pc(), 0x16);
+
+        // No stack left for the exception frame, the fault is reported to the host
+        let mut cpu = machine(ExceptionMode::Trap);
+        cpu.set_register(Register::SP, 0x0004);
+        assert!(matches!(cpu.step(), Err(CpuError::InvalidInstruction(..))));
+        assert_eq!(cpu.get_pc(), 0x14);
+        assert_eq!(cpu.get_sp(), 0x0004);
+    }
+}
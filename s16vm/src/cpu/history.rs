@@ -0,0 +1,169 @@
+use super::{bus::Access, protection::Protection, Result, CPU};
+
+// State before one step: registers and flags are small enough to be kept whole,
+// memory is kept as the bytes the step overwrote
+pub(super) struct UndoEntry {
+    registers: [u16; 8],
+    pc: u16,
+    sp: u16,
+    other_sp: u16,
+    flags: u16,
+    halted: bool,
+    exit_code: Option<u16>,
+    pending_interrupts: u8,
+    cycles: u64,
+    protection: Protection, // the guest may reconfigure it
+    writes: Vec<(u16, u8)>,
+    accesses: Vec<Access>,
+}
+
+impl CPU {
+    // Keep the last `limit` steps so they can be undone with `step_back`, 0 turns recording off.
+    // Device side effects (console output, timer counters) are not undone.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.history.len() > limit {
+            self.history.pop_front();
+        }
+    }
+
+    // Number of steps that can be undone
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    // Undoes the last recorded step, including a step which failed halfway.
+    // Returns the memory accesses made by the undone step, None if the history is empty.
+    pub fn step_back(&mut self) -> Option<Vec<Access>> {
+        let entry = self.history.pop_back()?;
+
+        self.bus.undo_writes(&entry.writes);
+        self.registers = entry.registers;
+        self.pc = entry.pc;
+        self.sp = entry.sp;
+        self.other_sp = entry.other_sp;
+        self.flags.set_u16(entry.flags);
+        self.halted = entry.halted;
+        self.exit_code = entry.exit_code;
+        self.pending_interrupts = entry.pending_interrupts;
+        self.cycles = entry.cycles;
+        self.protection = entry.protection;
+
+        Some(entry.accesses)
+    }
+
+    pub(super) fn step_recorded(&mut self) -> Result<bool> {
+        if self.halted {
+            return Ok(false);
+        }
+
+        let mut entry = UndoEntry {
+            registers: self.registers,
+            pc: self.pc,
+            sp: self.sp,
+            other_sp: self.other_sp,
+            flags: self.flags.as_u16(),
+            halted: self.halted,
+            exit_code: self.exit_code,
+            pending_interrupts: self.pending_interrupts,
+            cycles: self.cycles,
+            protection: self.protection,
+            writes: Vec::new(),
+            accesses: Vec::new(),
+        };
+        let logging = self.bus.is_access_logging();
+        self.bus.set_access_logging(true);
+        let first_access = self.bus.accesses().len();
+        self.bus.set_write_journal(true);
+
+        let result = self.step_once();
+
+        entry.writes = self.bus.take_journal();
+        self.bus.set_write_journal(false);
+        entry.accesses = self.bus.accesses()[first_access..].to_vec();
+        self.bus.set_access_logging(logging);
+
+        if self.history.len() == self.history_limit {
+            self.history.pop_front();
+        }
+        self.history.push_back(entry);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, cpu::error::CpuError};
+
+    #[test]
+    fn test_step_back_from_fault() {
+        // Recursion without a base case, the stack grows down into the code
+        // until a pushed value overwrites ADDI with a STOREO to the end of memory
+        let src = "
+            ADDI R1, 1
+        recurse:
+            PUSH R1
+            ADDI R1, 1
+            CALL recurse
+        ";
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(src, 0x0).unwrap(), 0x0).unwrap();
+        cpu.set_history_limit(100);
+
+        let err = cpu.run().unwrap_err();
+        assert!(matches!(err, CpuError::MemoryOutOfBounds(_)));
+        assert_eq!(cpu.history_len(), 100);
+
+        // the failed step is undone as well
+        assert!(cpu.step_back().is_some());
+        let pc = cpu.get_pc();
+        let sp = cpu.get_sp();
+
+        for _ in 0..99 {
+            assert!(cpu.step_back().is_some());
+        }
+        assert_eq!(cpu.step_back(), None);
+
+        // replaying the recorded steps arrives at the same state
+        for _ in 0..99 {
+            cpu.step().unwrap();
+        }
+        assert_eq!((cpu.get_pc(), cpu.get_sp()), (pc, sp));
+    }
+
+    #[test]
+    fn test_restores_memory_and_registers() {
+        let src = "
+            ADDI R1, 7
+            STORE R1, 0x40
+            PUSH R1
+            CMPI R1, 7
+            HALT
+        ";
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(src, 0x100).unwrap(), 0x100)
+            .unwrap();
+        cpu.set_history_limit(3);
+        cpu.run().unwrap();
+        assert_eq!(cpu.history_len(), 3);
+
+        let accesses = cpu.step_back().unwrap();
+        assert!(accesses.is_empty());
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.get_pc(), 0x108);
+
+        cpu.step_back();
+        assert!(!cpu.get_flags().to_string().contains("Z:1"));
+        cpu.step_back();
+        assert_eq!(cpu.get_sp(), 0xFFFE);
+        assert_eq!(cpu.get_bus().get_range(0xFFFC, 2), vec![0, 0]);
+        assert_eq!(cpu.get_bus().get_range(0x40, 2), vec![7, 0]);
+        assert_eq!(cpu.step_back(), None);
+
+        cpu.set_history_limit(0);
+        cpu.run().unwrap();
+        assert_eq!(cpu.history_len(), 0);
+    }
+}
@@ -1,4 +1,4 @@
-use super::{error::CpuError, instructions::{register::Register, Instruction, Jump}, types, CPU};
+use super::{error::CpuError, instructions::{register::Register, Instruction, Jump}, privilege::SYSCALL_VECTOR, syscall::{SyscallAction, SyscallContext}, types, CPU};
 
 type Result<T> = std::result::Result<T, CpuError>;
 
@@ -12,6 +12,24 @@ enum LogicalOperation {
 enum ShiftOperation {
     Left,
     Right,
+    RightArithmetic,
+    RotateLeft,
+    RotateRight,
+    RotateLeftCarry,
+    RotateRightCarry,
+}
+
+enum MultiplyOperation {
+    Low,
+    High,
+    HighUnsigned,
+}
+
+enum DivideOperation {
+    Quotient,
+    QuotientUnsigned,
+    Remainder,
+    RemainderUnsigned,
 }
 
 // Instruction implementations
@@ -20,26 +38,66 @@ impl CPU {
         match instruction {
             Instruction::Add { rd, rs, rt } => self.op_add(rd, rs, rt),
             Instruction::Sub { rd, rs, rt } => self.op_sub(rd, rs, rt),
+            Instruction::AddWithCarry { rd, rs, rt } => self.op_add_with_carry(rd, rs, rt),
+            Instruction::SubWithBorrow { rd, rs, rt } => {
+                self.op_sub_with_borrow(Some(rd), rs, rt)
+            }
+            Instruction::CmpWithBorrow { rs, rt } => self.op_sub_with_borrow(None, rs, rt),
 
             Instruction::And { rd, rs, rt } => self.op_logical(rd, rs, rt, LogicalOperation::And),
             Instruction::Or { rd, rs, rt } => self.op_logical(rd, rs, rt, LogicalOperation::Or),
             Instruction::Xor { rd, rs, rt } => self.op_logical(rd, rs, rt, LogicalOperation::Xor),
             Instruction::Not { rd, rt } => {
-                self.op_logical(rd, Register::R0, rt, LogicalOperation::Not)
+                self.op_logical(rd, rt, Register::R0, LogicalOperation::Not)
             }
 
             Instruction::Sll { rd, rs, rt } => self.op_shift(rd, rs, rt, ShiftOperation::Left),
             Instruction::Shr { rd, rs, rt } => self.op_shift(rd, rs, rt, ShiftOperation::Right),
+            Instruction::ShiftRightArithmetic { rd, rs } => {
+                self.op_shift(rd, rd, rs, ShiftOperation::RightArithmetic)
+            }
+            Instruction::RotateLeft { rd, rs } => {
+                self.op_shift(rd, rd, rs, ShiftOperation::RotateLeft)
+            }
+            Instruction::RotateRight { rd, rs } => {
+                self.op_shift(rd, rd, rs, ShiftOperation::RotateRight)
+            }
+            Instruction::RotateLeftCarry { rd, rs } => {
+                self.op_shift(rd, rd, rs, ShiftOperation::RotateLeftCarry)
+            }
+            Instruction::RotateRightCarry { rd, rs } => {
+                self.op_shift(rd, rd, rs, ShiftOperation::RotateRightCarry)
+            }
+
+            Instruction::Mul { rd, rs } => self.op_multiply(rd, rs, MultiplyOperation::Low),
+            Instruction::MulHigh { rd, rs } => self.op_multiply(rd, rs, MultiplyOperation::High),
+            Instruction::MulHighUnsigned { rd, rs } => {
+                self.op_multiply(rd, rs, MultiplyOperation::HighUnsigned)
+            }
+            Instruction::Div { rd, rs } => self.op_divide(rd, rs, DivideOperation::Quotient),
+            Instruction::DivUnsigned { rd, rs } => {
+                self.op_divide(rd, rs, DivideOperation::QuotientUnsigned)
+            }
+            Instruction::Rem { rd, rs } => self.op_divide(rd, rs, DivideOperation::Remainder),
+            Instruction::RemUnsigned { rd, rs } => {
+                self.op_divide(rd, rs, DivideOperation::RemainderUnsigned)
+            }
 
             Instruction::LoadIndirect { rd, rs } => self.op_load_indirect(rd, rs),
             Instruction::StoreIndirect { rd, rs } => self.op_store_indirect(rd, rs),
+            Instruction::LoadByte { rd, rs } => self.op_load_byte(rd, rs, true),
+            Instruction::LoadByteUnsigned { rd, rs } => self.op_load_byte(rd, rs, false),
+            Instruction::StoreByte { rd, rs } => self.op_store_byte(rd, rs),
 
             Instruction::Push { rs } => self.op_push(rs),
             Instruction::Pop { rd } => self.op_pop(rd),
 
             Instruction::Cmp { rs, rt } => self.op_cmp(rs, rt),
             Instruction::Jump { jump_type, offset } => self.op_jump(jump_type, offset),
+            Instruction::JumpRegister { rs } => self.op_jump_register(rs, false),
+            Instruction::CallRegister { rs } => self.op_jump_register(rs, true),
             Instruction::Return => self.op_ret(),
+            Instruction::ReturnFromInterrupt => self.op_reti(),
 
             Instruction::AddImmediate { rt, imm } => self.op_add_immediate(rt, imm),
             Instruction::AndImmediate { rt, imm } => self.op_and_immediate(rt, imm),
@@ -49,13 +107,15 @@ impl CPU {
 
             Instruction::Load { rt, addr } => self.op_load(rt, addr),
             Instruction::Store { rt, addr } => self.op_store(rt, addr),
+            Instruction::LoadOffset { rt, rs, offset } => self.op_load_offset(rt, rs, offset),
+            Instruction::StoreOffset { rt, rs, offset } => self.op_store_offset(rt, rs, offset),
 
             Instruction::MoveFromSpecial { rt, spec } => self.op_movs(rt, spec, false),
             Instruction::MoveFromToSpecial { rt, spec } => self.op_movs(rt, spec, true),
 
             Instruction::Nop => Ok(()),
             Instruction::Halt => self.op_halt(),
-            Instruction::Sysall => Err(CpuError::NotImplementedYet),
+            Instruction::Sysall => self.op_syscall(),
         }
     }
 
@@ -81,6 +141,44 @@ impl CPU {
         Ok(())
     }
 
+    // The carry chain instructions only ever clear Z, so that after ADD, ADC, ... or
+    // CMP, CMPC, ... it tells whether the whole multi-word result is zero
+    fn op_add_with_carry(&mut self, rd: Register, rs: Register, rt: Register) -> Result<()> {
+        let a = self.get_register(rs);
+        let b = self.get_register(rt);
+        let sum = a as u32 + b as u32 + self.flags.carry as u32;
+        let result = sum as u16;
+        let zero = self.flags.zero;
+
+        self.set_register(rd, result);
+        self.update_flags_arithmetic(a, b, result, sum > u16::MAX as u32, false);
+        self.flags.zero &= zero;
+
+        Ok(())
+    }
+
+    // SBC, or CMPC when there's no destination
+    fn op_sub_with_borrow(
+        &mut self,
+        rd: Option<Register>,
+        rs: Register,
+        rt: Register,
+    ) -> Result<()> {
+        let a = self.get_register(rs);
+        let b = self.get_register(rt);
+        let subtrahend = b as u32 + self.flags.carry as u32;
+        let result = (a as u32).wrapping_sub(subtrahend) as u16;
+        let zero = self.flags.zero;
+
+        if let Some(rd) = rd {
+            self.set_register(rd, result);
+        }
+        self.update_flags_arithmetic(a, b, result, (a as u32) < subtrahend, true);
+        self.flags.zero &= zero;
+
+        Ok(())
+    }
+
     fn op_logical(
         &mut self,
         rd: Register,
@@ -131,6 +229,29 @@ impl CPU {
                     ((value >> (s_size - 1)) & 1 == 1), // when shift to the left, a lower bit falls out
                 )
             }
+            ShiftOperation::RightArithmetic => (
+                ((value as i16) >> s_size) as u16,
+                ((value >> (s_size - 1)) & 1 == 1),
+            ),
+            // the bit rotated out of one end is the one that comes back at the other
+            ShiftOperation::RotateLeft => {
+                let result = value.rotate_left(s_size as u32);
+                (result, result & 1 == 1)
+            }
+            ShiftOperation::RotateRight => {
+                let result = value.rotate_right(s_size as u32);
+                (result, result & 0x8000 != 0)
+            }
+            // C takes part in the rotation as the 17th bit
+            ShiftOperation::RotateLeftCarry | ShiftOperation::RotateRightCarry => {
+                let wide = (self.flags.carry as u32) << 16 | value as u32;
+                let amount = match op {
+                    ShiftOperation::RotateLeftCarry => s_size as u32,
+                    _ => 17 - s_size as u32,
+                };
+                let rotated = (wide << amount | wide >> (17 - amount)) & 0x1FFFF;
+                (rotated as u16, rotated & 0x10000 != 0)
+            }
         };
 
         self.set_register(rd, result);
@@ -139,20 +260,108 @@ impl CPU {
         Ok(())
     }
 
+    // MUL sets C when the unsigned product doesn't fit in 16 bits and V when the signed one
+    // doesn't, the high halves only set Z and N
+    fn op_multiply(&mut self, rd: Register, rs: Register, op: MultiplyOperation) -> Result<()> {
+        let a = self.get_register(rd);
+        let b = self.get_register(rs);
+        let unsigned = a as u32 * b as u32;
+        let signed = a as i16 as i32 * b as i16 as i32;
+
+        let result = match op {
+            MultiplyOperation::Low => unsigned as u16,
+            MultiplyOperation::High => (signed >> 16) as u16,
+            MultiplyOperation::HighUnsigned => (unsigned >> 16) as u16,
+        };
+
+        self.set_register(rd, result);
+        self.update_flags_logical(result);
+        if let MultiplyOperation::Low = op {
+            self.flags.carry = unsigned > u16::MAX as u32;
+            self.flags.overflow = signed != result as i16 as i32;
+        }
+
+        Ok(())
+    }
+
+    // Signed division truncates toward zero, the remainder has the sign of the dividend.
+    // -32768 / -1 gives -32768 with V set.
+    fn op_divide(&mut self, rd: Register, rs: Register, op: DivideOperation) -> Result<()> {
+        let a = self.get_register(rd);
+        let b = self.get_register(rs);
+        if b == 0 {
+            return Err(CpuError::DivideByZero(self.pc.wrapping_sub(2)));
+        }
+
+        let (result, overflow) = match op {
+            DivideOperation::Quotient => {
+                let (quotient, overflow) = (a as i16).overflowing_div(b as i16);
+                (quotient as u16, overflow)
+            }
+            DivideOperation::QuotientUnsigned => (a / b, false),
+            DivideOperation::Remainder => ((a as i16).wrapping_rem(b as i16) as u16, false),
+            DivideOperation::RemainderUnsigned => (a % b, false),
+        };
+
+        self.set_register(rd, result);
+        self.update_flags_logical(result);
+        self.flags.overflow = overflow;
+
+        Ok(())
+    }
+
     fn op_halt(&mut self) -> Result<()> {
         self.halted = true;
         Ok(())
     }
 
+    // In user mode SYSCALL enters the kernel, the supervisor calls the host
+    fn op_syscall(&mut self) -> Result<()> {
+        if !self.flags.supervisor {
+            return self.enter_trap(SYSCALL_VECTOR, None);
+        }
+
+        let mut ctx = SyscallContext {
+            registers: &mut self.registers,
+            bus: &mut self.bus,
+        };
+
+        match self.syscalls.syscall(&mut ctx)? {
+            SyscallAction::Continue => {}
+            SyscallAction::Exit(code) => {
+                self.exit_code = Some(code);
+                self.halted = true;
+            }
+        }
+
+        Ok(())
+    }
+
     fn op_load_indirect(&mut self, rd: Register, rs: Register) -> Result<()> {
-        let value = self.memory.read_word(rs as u16)?;
+        let value = self.read_data_word(self.get_register(rs))?;
         self.set_register(rd, value);
         Ok(())
     }
 
     fn op_store_indirect(&mut self, rd: Register, rs: Register) -> Result<()> {
         let value = self.get_register(rd);
-        self.memory.write_word(rs as u16, value)?;
+        self.write_data_word(self.get_register(rs), value)?;
+        Ok(())
+    }
+
+    fn op_load_byte(&mut self, rd: Register, rs: Register, sign_extend: bool) -> Result<()> {
+        let byte = self.read_data_byte(self.get_register(rs))?;
+        let value = match sign_extend {
+            true => byte as i8 as u16,
+            false => byte as u16,
+        };
+        self.set_register(rd, value);
+        Ok(())
+    }
+
+    fn op_store_byte(&mut self, rd: Register, rs: Register) -> Result<()> {
+        let value = self.get_register(rd) as u8;
+        self.write_data_byte(self.get_register(rs), value)?;
         Ok(())
     }
 
@@ -166,28 +375,37 @@ impl CPU {
         Ok(())
     }
 
-    fn op_push(&mut self, rs: Register) -> Result<()> {
-        let value = self.get_register(rs);
+    pub(super) fn op_push(&mut self, rs: Register) -> Result<()> {
+        self.push_word(self.get_register(rs))
+    }
+
+    pub(super) fn push_word(&mut self, value: u16) -> Result<()> {
         if self.sp < 2 {
             return Err(CpuError::StackOverflow);
         }
 
         self.sp = self.sp.wrapping_sub(2);
-        self.memory.write_word(self.sp, value)?;
+        self.write_data_word(self.sp, value)?;
+
+        Ok(())
+    }
+
+    pub(super) fn op_pop(&mut self, rd: Register) -> Result<()> {
+        let value = self.pop_word()?;
+        self.set_register(rd, value);
 
         Ok(())
     }
 
-    fn op_pop(&mut self, rd: Register) -> Result<()> {
+    fn pop_word(&mut self) -> Result<u16> {
         if self.sp > 0xFFFE {
             return Err(CpuError::StackOverflow);
         }
 
-        let value = self.memory.read_word(self.sp)?;
-        self.set_register(rd, value);
+        let value = self.read_data_word(self.sp)?;
         self.sp = self.sp.wrapping_add(2);
 
-        Ok(())
+        Ok(value)
     }
 
     fn op_ret(&mut self) -> Result<()> {
@@ -196,34 +414,55 @@ impl CPU {
         Ok(())
     }
 
-    // Remember offset is 12 bits!
+    // Reverse of trap entry: FLAGS was pushed last. Both are popped from the supervisor
+    // stack before FLAGS may switch back to user mode.
+    fn op_reti(&mut self) -> Result<()> {
+        self.check_privileged()?;
+        let flags = self.pop_word()?;
+        self.pc = self.pop_word()?;
+        self.set_flags(flags);
+
+        Ok(())
+    }
+
+    // Remember offset is 12 bits! Branches with a shorter one have it sign extended.
     fn op_jump(&mut self, jt: Jump, offset: u16) -> Result<()> {
         let signed_offset = types::convert_12bit_to_signed(offset);
+        let flags = self.flags;
 
-        match jt {
+        let taken = match jt {
             Jump::Call => {
                 self.op_push(Register::PC)?;
-                self.set_register(Register::PC, self.pc.wrapping_add_signed(signed_offset));
-            }
-            Jump::Unconditional => {
-                self.set_register(Register::PC, self.pc.wrapping_add_signed(signed_offset));
-            }
-            Jump::Zero => {
-                if self.flags.zero {
-                    self.set_register(Register::PC, self.pc.wrapping_add_signed(signed_offset));
-                }
-            }
-            Jump::NotZero => {
-                if !self.flags.zero {
-                    self.set_register(Register::PC, self.pc.wrapping_add_signed(signed_offset));
-                }
-            }
-            Jump::GreaterThan => {
-                if !self.flags.zero && self.flags.negative == self.flags.overflow {
-                    self.set_register(Register::PC, self.pc.wrapping_add_signed(signed_offset));
-                }
+                true
             }
+            Jump::Unconditional => true,
+            Jump::Zero => flags.zero,
+            Jump::NotZero => !flags.zero,
+            Jump::GreaterThan => !flags.zero && flags.negative == flags.overflow,
+            Jump::LessThan => flags.negative != flags.overflow,
+            Jump::GreaterOrEqual => flags.negative == flags.overflow,
+            Jump::LessOrEqual => flags.zero || flags.negative != flags.overflow,
+            Jump::Carry => flags.carry,
+            Jump::NotCarry => !flags.carry,
+            Jump::Negative => flags.negative,
+            Jump::Overflow => flags.overflow,
+            Jump::Above => !flags.carry && !flags.zero,
+        };
+
+        if taken {
+            self.set_register(Register::PC, self.pc.wrapping_add_signed(signed_offset));
+        }
+
+        Ok(())
+    }
+
+    // CALLR pushes the address of the next instruction like CALL does
+    fn op_jump_register(&mut self, rs: Register, call: bool) -> Result<()> {
+        let target = self.get_register(rs);
+        if call {
+            self.op_push(Register::PC)?;
         }
+        self.set_register(Register::PC, target);
 
         Ok(())
     }
@@ -253,7 +492,7 @@ impl CPU {
     fn op_or_immediate(&mut self, rt: Register, imm: u8) -> Result<()> {
         let value = self.get_register(rt);
         let imm = imm as u16;
-        let result = value & imm;
+        let result = value | imm;
 
         self.set_register(rt, result);
         self.update_flags_logical(result);
@@ -280,7 +519,7 @@ impl CPU {
     }
 
     fn op_load(&mut self, rt: Register, addr: u8) -> Result<()> {
-        let value = self.memory.read_word(addr as u16)?;
+        let value = self.read_data_word(addr as u16)?;
         self.set_register(rt, value);
 
         Ok(())
@@ -288,12 +527,33 @@ impl CPU {
 
     fn op_store(&mut self, rt: Register, addr: u8) -> Result<()> {
         let value = self.get_register(rt);
-        self.memory.write_word(addr as u16, value)?;
+        self.write_data_word(addr as u16, value)?;
 
         Ok(())
     }
 
+    fn op_load_offset(&mut self, rt: Register, rs: Register, offset: i8) -> Result<()> {
+        let address = self.get_register(rs).wrapping_add_signed(offset as i16);
+        let value = self.read_data_word(address)?;
+        self.set_register(rt, value);
+
+        Ok(())
+    }
+
+    fn op_store_offset(&mut self, rt: Register, rs: Register, offset: i8) -> Result<()> {
+        let address = self.get_register(rs).wrapping_add_signed(offset as i16);
+        let value = self.get_register(rt);
+        self.write_data_word(address, value)?;
+
+        Ok(())
+    }
+
+    // Writes to SP and FLAGS and any access to USP are privileged
     fn op_movs(&mut self, rt: Register, spec: Register, to_special: bool) -> Result<()> {
+        if spec == Register::USP || (to_special && spec != Register::PC) {
+            self.check_privileged()?;
+        }
+
         let (source, target) = match to_special {
             true => (rt, spec),
             false => (spec, rt),
@@ -305,3 +565,328 @@ impl CPU {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn run(source: &str) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(source, 0).unwrap(), 0).unwrap();
+        cpu.run().unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_indirect() {
+        // the address is the value of Rs, not the register number
+        let cpu = run("
+            LUI    R2, 0x01
+            ADDI   R1, 42
+            STOREI R1, R2
+            LOADI  R3, R2
+            HALT
+        ");
+        assert_eq!(cpu.get_bus().get_range(0x100, 2), vec![42, 0]);
+        assert_eq!(cpu.get_registers()[3], 42);
+    }
+
+    #[test]
+    fn test_or_immediate() {
+        let cpu = run("
+            ADDI R1, 0x0F
+            ORI  R1, 0x30
+            HALT
+        ");
+        assert_eq!(cpu.get_registers()[1], 0x3F);
+    }
+
+    #[test]
+    fn test_multiply() {
+        // 300 * 300 = 90000 = 0x15F90, -2 * 3 = -6
+        let cpu = run("
+            ADDI R1, 100
+            ADDI R2, 3
+            MUL  R1, R2
+            ADD  R3, R1, R0
+            MUL  R3, R1
+            ADD  R4, R1, R0
+            MULHU R4, R1
+            ADDI R5, -2
+            ADD  R6, R5, R0
+            MULH R6, R2
+            MUL  R5, R2
+            HALT
+        ");
+        let r = cpu.get_registers();
+        assert_eq!(r[3], 0x5F90);
+        assert_eq!(r[4], 0x0001);
+        assert_eq!(r[5], 0xFFFA);
+        assert_eq!(r[6], 0xFFFF);
+        // -6 fits in 16 bits signed, but not 0xFFFE * 3 unsigned
+        assert!(cpu.get_flags().carry());
+        assert!(!cpu.get_flags().overflow());
+        assert!(cpu.get_flags().negative());
+
+        let cpu = run("ADDI R1, 100\n ADDI R2, 3\n MUL R1, R2\n MUL R1, R1\n HALT");
+        assert!(cpu.get_flags().carry() && cpu.get_flags().overflow());
+    }
+
+    #[test]
+    fn test_divide() {
+        // -7 / 2 = -3 rem -1, 0xFFF9 / 2 = 0x7FFC rem 1
+        let cpu = run("
+            ADDI R1, -7
+            ADDI R2, 2
+            ADD  R3, R1, R0
+            DIV  R3, R2
+            ADD  R4, R1, R0
+            REM  R4, R2
+            ADD  R5, R1, R0
+            DIVU R5, R2
+            ADD  R6, R1, R0
+            REMU R6, R2
+            HALT
+        ");
+        let r = cpu.get_registers();
+        assert_eq!(r[3], -3i16 as u16);
+        assert_eq!(r[4], -1i16 as u16);
+        assert_eq!(r[5], 0x7FFC);
+        assert_eq!(r[6], 1);
+
+        let cpu = run("LUI R1, 0x80\n ADDI R2, -1\n DIV R1, R2\n HALT");
+        assert_eq!(cpu.get_registers()[1], 0x8000);
+        assert!(cpu.get_flags().overflow());
+
+        let mut cpu = CPU::new();
+        let program = assemble("ADDI R1, 1\n REMU R1, R0\n HALT", 0).unwrap();
+        cpu.load_program(program, 0).unwrap();
+        assert!(matches!(cpu.run(), Err(CpuError::DivideByZero(0x0002))));
+        assert_eq!(cpu.get_registers()[1], 1);
+    }
+
+    #[test]
+    fn test_bytes() {
+        // Copies the zero-terminated string at `text` to 0x80, then reads a byte back signed
+        let cpu = run("
+            JMP   start
+        text:
+            .byte 0x41, 0x42, 0xE9, 0
+        start:
+            ADDI  R1, 2         # text
+            ADDI  R2, 0x7F
+            ADDI  R2, 1
+        copy:
+            LOADBU R3, R1
+            STOREB R3, R2
+            ADDI  R1, 1
+            ADDI  R2, 1
+            CMPI  R3, 0
+            JNZ   copy
+            ADDI  R1, -2
+            LOADB R4, R1
+            LOADBU R5, R1
+            HALT
+        ");
+        assert_eq!(cpu.get_bus().get_range(0x80, 5), vec![0x41, 0x42, 0xE9, 0, 0]);
+        assert_eq!(cpu.get_registers()[4], 0xFFE9);
+        assert_eq!(cpu.get_registers()[5], 0x00E9);
+    }
+
+    #[test]
+    fn test_base_offset() {
+        // a frame pointer at 0x0100 with a local below it and an argument above it
+        let cpu = run("
+            LUI    R6, 0x01
+            ADDI   R1, 42
+            ADDI   R2, -1
+            STOREO R1, [R6-16]
+            STOREO R2, [R6+14]
+            LOADO  R3, [R6+14]
+            LOADO  R4, [R6-16]
+            STOREO R4, [R6]
+            HALT
+        ");
+        assert_eq!(cpu.get_bus().get_range(0xF0, 2), vec![42, 0]);
+        assert_eq!(cpu.get_bus().get_range(0x100, 2), vec![42, 0]);
+        assert_eq!(cpu.get_bus().get_range(0x10E, 2), vec![0xFF, 0xFF]);
+        assert_eq!(cpu.get_registers()[3], 0xFFFF);
+        assert_eq!(cpu.get_registers()[4], 42);
+    }
+
+    #[test]
+    fn test_not() {
+        let cpu = run("
+            ORI  R1, 0x0F
+            NOT  R2, R1
+            HALT
+        ");
+        assert_eq!(cpu.get_registers()[2], 0xFFF0);
+        // the operand is in the RT field
+        assert_eq!(cpu.get_bus().get_range(0x2, 2), vec![0x0D, 0x04]);
+    }
+
+    #[test]
+    fn test_branches() {
+        // (a, b, branches taken after CMP a, b)
+        let cases: [(u16, u16, &[&str]); 5] = [
+            (1, 2, &["JLT", "JLE", "JC", "JB", "JN"]),
+            (2, 1, &["JGE", "JNC", "JA"]),
+            (2, 2, &["JGE", "JLE", "JNC"]),
+            (0x8000, 1, &["JLT", "JLE", "JNC", "JV", "JA"]),
+            (0xFFFF, 1, &["JLT", "JLE", "JNC", "JN", "JA"]),
+        ];
+        let branches = ["JLT", "JGE", "JLE", "JC", "JB", "JNC", "JN", "JV", "JA"];
+
+        for (a, b, taken) in cases {
+            for branch in branches {
+                let source = format!(
+                    "CMP R1, R2\n {} yes\n HALT\n yes: ADDI R3, 1\n HALT",
+                    branch
+                );
+                let mut cpu = CPU::new();
+                cpu.load_program(assemble(&source, 0).unwrap(), 0).unwrap();
+                cpu.set_register(Register::R1, a);
+                cpu.set_register(Register::R2, b);
+                cpu.run().unwrap();
+                assert_eq!(
+                    cpu.get_registers()[3] == 1,
+                    taken.contains(&branch),
+                    "{} after CMP 0x{:04X}, 0x{:04X}",
+                    branch,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_register_jumps() {
+        // Calls `double` through a register, then leaves through a jump table entry
+        let cpu = run("
+            JMP   start
+        double:
+            ADD   R2, R2, R2
+            RET
+        table:
+            .word double, done
+        start:
+            ADDI  R2, 21
+            LOAD  R1, table
+            CALLR R1
+            LOAD  R1, 8         # table + 2
+            JR    R1
+            ADDI  R2, 1
+        done:
+            HALT
+        ");
+        assert_eq!(cpu.get_registers()[2], 42);
+        assert_eq!(cpu.get_sp(), 0xFFFE);
+    }
+
+    #[test]
+    fn test_carry_chain() {
+        // 0x0001FFFF + 0x00000001, then back, then compare equal high words
+        let cpu = run("
+            ADDI R1, -1
+            ADDI R2, 1
+            ADDI R3, 1
+            ADD  R1, R1, R3
+            ADC  R2, R2, R0
+            ADD  R5, R1, R0
+            ADD  R6, R2, R0
+            SUB  R5, R5, R3
+            SBC  R6, R6, R0
+            CMP  R3, R0
+            CMPC R2, R2
+            HALT
+        ");
+        let r = cpu.get_registers();
+        assert_eq!((r[1], r[2]), (0x0000, 0x0002));
+        assert_eq!((r[5], r[6]), (0xFFFF, 0x0001));
+        // low words differ, so Z stays clear although the high words are equal
+        assert!(!cpu.get_flags().zero());
+        assert!(!cpu.get_flags().carry());
+    }
+
+    #[test]
+    fn test_shifts_and_rotates() {
+        // (instruction, value, amount, C before, result, C after)
+        for (mnemonic, value, amount, carry, result, carry_out) in [
+            ("SRA", 0x8001, 1, false, 0xC000, true),
+            ("SRA", 0x8000, 15, false, 0xFFFF, false),
+            ("SRA", 0x4000, 14, false, 0x0001, false),
+            ("ROL", 0x8001, 1, false, 0x0003, true),
+            ("ROR", 0x8001, 4, false, 0x1800, false),
+            ("ROR", 0x0001, 1, false, 0x8000, true),
+            ("RCL", 0x8000, 1, false, 0x0000, true),
+            ("RCL", 0x0000, 1, true, 0x0001, false),
+            ("RCR", 0x0001, 1, true, 0x8000, true),
+            ("RCR", 0x0002, 2, true, 0x4000, true),
+            // only the low 4 bits of the amount are used, 0 changes nothing
+            ("ROL", 0x1234, 16, true, 0x1234, true),
+        ] {
+            let mut cpu = CPU::new();
+            let source = format!("{} R1, R2\n HALT", mnemonic);
+            cpu.load_program(assemble(&source, 0).unwrap(), 0).unwrap();
+            cpu.set_register(Register::R1, value);
+            cpu.set_register(Register::R2, amount);
+            cpu.set_register(Register::FLAGS, (carry as u16) << 1 | 0x20);
+            cpu.run().unwrap();
+
+            let name = format!("{} 0x{:04X}, {}", mnemonic, value, amount);
+            assert_eq!(cpu.get_registers()[1], result, "{}", name);
+            assert_eq!(cpu.get_flags().carry(), carry_out, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_math32() {
+        let library = include_str!("../../lib/math32.s");
+        let values: [u32; 9] = [
+            0, 1, 3, 0xFFFF, 0x10000, 0x7FFF_FFFF, 0x8000_0000, 0xDEAD_BEEF, 0xFFFF_FFFF,
+        ];
+
+        let call = |routine: &str, a: u32, b: u32| {
+            let source = format!("CALL {}\n HALT\n{}", routine, library);
+            let mut cpu = CPU::new();
+            cpu.load_program(assemble(&source, 0).unwrap(), 0).unwrap();
+            for (register, value) in [
+                (Register::R1, a as u16),
+                (Register::R2, (a >> 16) as u16),
+                (Register::R3, b as u16),
+                (Register::R4, (b >> 16) as u16),
+            ] {
+                cpu.set_register(register, value);
+            }
+            cpu.run().unwrap();
+            cpu
+        };
+        let pair = |cpu: &CPU, low: usize| {
+            cpu.get_registers()[low] as u32 | (cpu.get_registers()[low + 1] as u32) << 16
+        };
+
+        for a in values {
+            for b in values {
+                assert_eq!(pair(&call("add32", a, b), 1), a.wrapping_add(b));
+                assert_eq!(pair(&call("sub32", a, b), 1), a.wrapping_sub(b));
+                assert_eq!(pair(&call("neg32", a, b), 1), a.wrapping_neg());
+                assert_eq!(pair(&call("mul32", a, b), 1), a.wrapping_mul(b));
+
+                let cpu = call("udiv32", a, b);
+                let (quotient, remainder) = match b {
+                    0 => (u32::MAX, a),
+                    _ => (a / b, a % b),
+                };
+                assert_eq!((pair(&cpu, 1), pair(&cpu, 5)), (quotient, remainder));
+
+                let flags = *call("cmp32", a, b).get_flags();
+                assert_eq!(flags.zero(), a == b);
+                assert_eq!(flags.carry(), a < b);
+                assert_eq!(flags.negative() != flags.overflow(), (a as i32) < (b as i32));
+            }
+        }
+    }
+}
@@ -1,19 +1,34 @@
 pub mod error;
+pub mod isa;
 pub mod register;
 pub mod word;
 
 use error::InstructionError;
 use register::Register as R;
-use word::Word;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Jump {
     Call,          // CALL
     Unconditional, // JMP
     Zero,          // JZ
     NotZero,       // JNZ
     GreaterThan,   // JGT
+
+    // Branches sharing opcode 0xE, told apart by a condition field and limited to 9-bit offsets
+    LessThan,       // JLT
+    GreaterOrEqual, // JGE
+    LessOrEqual,    // JLE
+    Carry,          // JC, also JB: unsigned below after CMP
+    NotCarry,       // JNC
+    Negative,       // JN
+    Overflow,       // JV
+    Above,          // JA: unsigned above after CMP
 }
 
+// Offsets of LOADO and STOREO, 5 bits of two's complement below the base register
+pub const BASE_OFFSET_RANGE: std::ops::RangeInclusive<i8> = -16..=15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     Add { rd: R, rs: R, rt: R },
     Sub { rd: R, rs: R, rt: R },
@@ -29,6 +44,8 @@ pub enum Instruction {
     Return,
     Push { rs: R },
     Pop { rd: R },
+    AddWithCarry { rd: R, rs: R, rt: R },
+    SubWithBorrow { rd: R, rs: R, rt: R },
 
     AddImmediate { rt: R, imm: i8 },
     AndImmediate { rt: R, imm: u8 },
@@ -38,160 +55,83 @@ pub enum Instruction {
     Load { rt: R, addr: u8 },
     Store { rt: R, addr: u8 },
 
+    // Base+offset addressing: Rt = Memory[Rs + offset], Memory[Rs + offset] = Rt
+    LoadOffset { rt: R, rs: R, offset: i8 },
+    StoreOffset { rt: R, rs: R, offset: i8 },
+
     Jump { jump_type: Jump, offset: u16 },
 
     MoveFromSpecial { rt: R, spec: R },
     MoveFromToSpecial { rt: R, spec: R },
 
+    // Two-operand arithmetic: Rd = Rd op Rs
+    Mul { rd: R, rs: R },
+    MulHigh { rd: R, rs: R },
+    MulHighUnsigned { rd: R, rs: R },
+    Div { rd: R, rs: R },
+    DivUnsigned { rd: R, rs: R },
+    Rem { rd: R, rs: R },
+    RemUnsigned { rd: R, rs: R },
+
+    // Two-operand shifts and rotates: Rd = Rd op (Rs & 0xF)
+    ShiftRightArithmetic { rd: R, rs: R },
+    RotateLeft { rd: R, rs: R },
+    RotateRight { rd: R, rs: R },
+    RotateLeftCarry { rd: R, rs: R },
+    RotateRightCarry { rd: R, rs: R },
+
+    // Compare of the next word after CMP or CMPC
+    CmpWithBorrow { rs: R, rt: R },
+
+    // Jumps to the address in Rs, CALLR pushes the return address first
+    JumpRegister { rs: R },
+    CallRegister { rs: R },
+
+    // Byte access: Rd = Memory[Rs] sign or zero extended, Memory[Rs] = low byte of Rd
+    LoadByte { rd: R, rs: R },
+    LoadByteUnsigned { rd: R, rs: R },
+    StoreByte { rd: R, rs: R },
+
     // System operations
     Nop,
+    ReturnFromInterrupt,
     Halt,
     Sysall,
 }
 
 type Result<T> = std::result::Result<T, InstructionError>;
 
-impl std::fmt::Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use Instruction::*;
-        match self {
-            Add { rd, rs, rt } => write!(f, "ADD {}, {}, {}", rd, rs, rt),
-            Sub { rd, rs, rt } => write!(f, "SUB {}, {}, {}", rd, rs, rt),
-            And { rd, rs, rt } => write!(f, "AND {}, {}, {}", rd, rs, rt),
-            Or { rd, rs, rt } => write!(f, "OR {}, {}, {}", rd, rs, rt),
-            Xor { rd, rs, rt } => write!(f, "XOR {}, {}, {}", rd, rs, rt),
-            Not { rd, rt } => write!(f, "NOT {}, {}", rd, rt),
-            Sll { rd, rs, rt } => write!(f, "Sll"),
-            Shr { rd, rs, rt } => write!(f, "Shr"),
-            LoadIndirect { rd, rs } => write!(f, "LoadIndirect"),
-            StoreIndirect { rd, rs } => write!(f, "StoreIndirect"),
-            Cmp { rs, rt } => write!(f, "Cmp"),
-            Return => write!(f, "Return"),
-            Push { rs } => write!(f, "Push"),
-            Pop { rd } => write!(f, "Pop"),
-            AddImmediate { rt, imm } => write!(f, "ADDI {}, {}", rt, imm),
-            AndImmediate { rt, imm } => write!(f, "AndImmediate"),
-            OrImmediate { rt, imm } => write!(f, "ORI {}, {}", rt, imm),
-            LoadUperImmediate { rt, imm } => write!(f, "LoadUperImmediate"),
-            CmpImmediate { rt, imm } => write!(f, "CmpImmediate"),
-            Load { rt, addr } => write!(f, "LOAD {}, 0x{:04X}", rt, addr),
-            Store { rt, addr } => write!(f, "STORE {}, 0x{:04X}", rt, addr),
-            Jump { jump_type, offset } => write!(f, "Jump"),
-            MoveFromSpecial { rt, spec } => write!(f, "MoveFromSpecial"),
-            MoveFromToSpecial { rt, spec } => write!(f, "MoveFromToSpecial"),
-            Nop => write!(f, "Nop"),
-            Halt => write!(f, "HALT"),
-            Sysall => write!(f, "Sysall"),
+// Opcodes, functs, mnemonics and operand syntax of every variant are in the table in `isa`,
+// which generates `decode`, `encode`, `mnemonic` and `Display`.
+
+impl Jump {
+    pub fn mnemonic(self) -> &'static str {
+        self.instruction().mnemonic()
+    }
+
+    // Offsets that can be encoded, in bytes relative to the next instruction
+    pub fn offset_range(self) -> std::ops::RangeInclusive<i16> {
+        let width = isa::operand_width(&self.instruction(), "offset")
+            .expect("jump entries have an offset field");
+        isa::signed_range(width)
+    }
+
+    fn instruction(self) -> Instruction {
+        Instruction::Jump {
+            jump_type: self,
+            offset: 0,
         }
     }
 }
 
-impl Instruction {
-    pub fn decode(w: Word) -> Result<Instruction> {
-        let instrruction = match w {
-            Word::RType {
-                opcode,
-                rd,
-                rs,
-                rt,
-                funct,
-            } => {
-                let rd = R::new(rd)?;
-                let rs = R::new(rs)?;
-                let rt = R::new(rt)?;
-
-                match (opcode, funct) {
-                    (0x0, 0x0) => Instruction::Add { rd, rs, rt },
-                    (0x0, 0x1) => Instruction::Sub { rd, rs, rt },
-                    (0x0, 0x2) => Instruction::And { rd, rs, rt },
-                    (0x0, 0x3) => Instruction::Or { rd, rs, rt },
-                    (0x0, 0x4) => Instruction::Xor { rd, rs, rt },
-                    (0x0, 0x5) => Instruction::Not { rd, rt },
-                    (0x0, 0x6) => Instruction::Sll { rd, rs, rt },
-                    (0x0, 0x7) => Instruction::Shr { rd, rs, rt },
-                    (0x1, 0x0) => Instruction::LoadIndirect { rd, rs },
-                    (0x1, 0x1) => Instruction::StoreIndirect { rd, rs },
-                    (0x1, 0x2) => Instruction::Cmp { rs, rt },
-                    (0x1, 0x3) => Instruction::Return,
-                    (0x1, 0x4) => Instruction::Push { rs },
-                    (0x1, 0x5) => Instruction::Pop { rd },
-                    _ => return Err(InstructionError::InvalidRType(opcode, funct)),
-                }
-            }
-
-            Word::IType { opcode, rt, imm } => {
-                let rt = R::new(rt)?;
-
-                match opcode {
-                    0x2 => Instruction::Load { rt, addr: imm },
-                    0x3 => Instruction::Store { rt, addr: imm },
-                    0x4 => Instruction::AddImmediate { rt, imm: imm as i8 },
-                    0x5 => Instruction::AndImmediate { rt, imm },
-                    0x6 => Instruction::OrImmediate { rt, imm },
-                    0x7 => Instruction::LoadUperImmediate { rt, imm },
-                    0x8 => Instruction::CmpImmediate { rt, imm: imm as i8 },
-                    _ => return Err(InstructionError::InvalidIType(opcode)),
-                }
-            }
-
-            Word::JType { opcode, offset } => {
-                use Jump::*;
-                match opcode {
-                    0x9 => Instruction::Jump {
-                        jump_type: Call,
-                        offset,
-                    },
-                    0xA => Instruction::Jump {
-                        jump_type: Unconditional,
-                        offset,
-                    },
-                    0xB => Instruction::Jump {
-                        jump_type: Zero,
-                        offset,
-                    },
-                    0xC => Instruction::Jump {
-                        jump_type: NotZero,
-                        offset,
-                    },
-                    0xD => Instruction::Jump {
-                        jump_type: GreaterThan,
-                        offset,
-                    },
-                    _ => return Err(InstructionError::InvalidJType(opcode)),
-                }
-            }
-
-            Word::EType { subcode, rs, rt } => match subcode {
-                0x0 => Instruction::Nop,
-                0xE => Instruction::Sysall,
-                0xF => Instruction::Halt,
-
-                0x1 => {
-                    // MOVS Rt, SPEC ; instruction is [0xF][SUB][Rs][Rt][0]
-                    let spec = match rt {
-                        0 => R::PC,
-                        1 => R::SP,
-                        2 => R::FLAGS,
-                        _ => return Err(InstructionError::InvalidSpecialRegister(rt)),
-                    };
-                    let rt = R::new(rs)?;
-                    Instruction::MoveFromSpecial { rt, spec }
-                }
-                0x2 => {
-                    // MOVS SPEC, Rt ; instruction is [0xF][SUB][Rs][Rt][0]
-                    let rt = R::new(rt)?;
-                    let spec = match rs {
-                        0 => R::PC,
-                        1 => R::SP,
-                        2 => R::FLAGS,
-                        _ => return Err(InstructionError::InvalidSpecialRegister(rs)),
-                    };
-                    Instruction::MoveFromToSpecial { rt, spec }
-                }
-                _ => return Err(InstructionError::InvalidEType(subcode)),
-            },
-        };
-
-        Ok(instrruction)
+fn gpr(reg: R) -> Result<u8> {
+    if reg.is_special() {
+        return Err(InstructionError::InvalidRegister(reg.idx()));
     }
+    Ok(reg.idx())
+}
+
+fn special(reg: R) -> Result<u8> {
+    reg.special_idx()
+        .ok_or(InstructionError::InvalidSpecialRegister(reg.idx()))
 }
@@ -1,14 +1,14 @@
-use super::word::Word;
-
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum InstructionError {
     InvalidRType(u8, u8),
     InvalidIType(u8),
     InvalidJType(u8),
-    InvalidEType(u8),
+    InvalidEType(u8, u8),
 
     InvalidRegister(u8),
     InvalidSpecialRegister(u8),
+    OffsetOutOfRange(i16),
 }
 
 impl std::fmt::Display for InstructionError {
@@ -19,12 +19,17 @@ impl std::fmt::Display for InstructionError {
             }
             InstructionError::InvalidIType(op) => write!(f, "invalid i-type OP=0x{:X}", op),
             InstructionError::InvalidJType(op) => write!(f, "invalid j-type OP=0x{:X}", op),
-            InstructionError::InvalidEType(sub) => write!(f, "invalid e-type OP=0x{:X}", sub),
+            InstructionError::InvalidEType(sub, funct) => {
+                write!(f, "invalid e-type SUB=0x{:X} FUNCT=0x{:X}", sub, funct)
+            }
             InstructionError::InvalidRegister(idx) => {
                 write!(f, "register must be 0-7, given {}", idx)
             }
             InstructionError::InvalidSpecialRegister(idx) => {
-                write!(f, "special register must be 0-2, given {}", idx)
+                write!(f, "special register must be 0-3, given {}", idx)
+            }
+            InstructionError::OffsetOutOfRange(offset) => {
+                write!(f, "offset {} doesn't fit the instruction", offset)
             }
         }
     }
@@ -0,0 +1,816 @@
+use std::{fmt, marker::PhantomData, ops::RangeInclusive};
+
+use super::{
+    error::InstructionError, gpr, register::Register as R, special, word::Word, Instruction, Jump,
+    Result, BASE_OFFSET_RANGE,
+};
+use crate::cpu::types::convert_12bit_to_signed;
+
+// The instruction table. Every entry is
+//
+//     MNEMONIC, Variant { operand: Kind @ shift, ... } [where field = constant],
+//         fixed bits, operand syntax, description;
+//
+// Operands are listed in the order they are written in assembly, the syntax has a `{}` for
+// each of them. The fixed bits are a (value, mask) pair built by the helpers below, bits that
+// are neither fixed nor part of an operand are ignored when decoding and encoded as zero.
+//
+// `Instruction::decode`, `Instruction::encode`, `Instruction::mnemonic`, `Display` and the
+// reference in docs/isa.md are all generated from it.
+macro_rules! isa {
+    ($(
+        $mnemonic:literal,
+        $variant:ident { $($field:ident: $kind:ident @ $shift:expr),* }
+        $(where $fixed:ident = $value:path)?,
+        $encoding:expr, $syntax:literal, $description:literal;
+    )*) => {
+        impl Instruction {
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    $(Instruction::$variant { $($fixed: $value,)? .. } => $mnemonic,)*
+                }
+            }
+
+            // Row of the instruction in the table, e.g. to keep settings per instruction
+            pub(crate) fn entry(&self) -> usize {
+                match self {
+                    $(Instruction::$variant { $($fixed: $value,)? .. } => {
+                        const ENTRY: usize = entry($encoding);
+                        ENTRY
+                    })*
+                }
+            }
+
+            pub fn decode(w: Word) -> Result<Instruction> {
+                let bits = w.to_bits();
+                $({
+                    let (value, mask) = $encoding;
+                    if bits & mask == value {
+                        return Ok(Instruction::$variant {
+                            $($field: $kind::decode(bits >> $shift & field_mask($kind::WIDTH))?,)*
+                            $($fixed: $value,)?
+                        });
+                    }
+                })*
+
+                Err(match w {
+                    Word::RType { opcode, funct, .. } => {
+                        InstructionError::InvalidRType(opcode, funct)
+                    }
+                    Word::IType { opcode, .. } => InstructionError::InvalidIType(opcode),
+                    Word::JType { opcode, .. } => InstructionError::InvalidJType(opcode),
+                    Word::EType { subcode, funct, .. } => {
+                        InstructionError::InvalidEType(subcode, funct)
+                    }
+                })
+            }
+
+            // Inverse of `decode`: packs the instruction back into a word.
+            // Fields not used by an instruction are encoded as zero.
+            pub fn encode(&self) -> Result<Word> {
+                let bits = match *self {
+                    $(Instruction::$variant { $($field,)* $($fixed: $value,)? } => {
+                        let (value, _) = $encoding;
+                        value $(| $kind::encode($field)? << $shift)*
+                    })*
+                };
+
+                Ok(Word::new(bits))
+            }
+        }
+
+        impl fmt::Display for Instruction {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match *self {
+                    $(Instruction::$variant { $($field,)* $($fixed: $value,)? } => {
+                        f.write_str($mnemonic)?;
+                        isa!(@space f $($field)*);
+                        write!(f, $syntax $(, Show::<$kind>($field, PhantomData))*)
+                    })*
+                }
+            }
+        }
+
+        const ENTRIES: &[Entry] = &[$(
+            Entry {
+                mnemonic: $mnemonic,
+                syntax: $syntax,
+                value: $encoding.0,
+                mask: $encoding.1,
+                operands: &[$(
+                    Operand {
+                        field: stringify!($field),
+                        name: $kind::name,
+                        letter: $kind::letter,
+                        shift: $shift,
+                        width: $kind::WIDTH,
+                    }
+                ),*],
+                description: $description,
+            }
+        ),*];
+    };
+
+    // The mnemonic is separated from the operands, if there are any
+    (@space $f:ident) => {};
+    (@space $f:ident $($field:ident)+) => {
+        $f.write_str(" ")?;
+    };
+}
+
+// Operand positions in the word
+const RD: u32 = 9; // also RT of I-type instructions
+const RS: u32 = 6;
+const RT: u32 = 3;
+const SUB_RS: u32 = 5; // also the base register of LOADO and STOREO
+const SUB_RT: u32 = 2;
+const LOW: u32 = 0; // immediates and offsets
+
+isa! {
+    // R-type: [OPCODE][RD][RS][RT][FUNCT]
+    "ADD", Add { rd: Gpr @ RD, rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x0, 0x0), "{}, {}, {}", "Rd = Rs + Rt";
+    "SUB", Sub { rd: Gpr @ RD, rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x0, 0x1), "{}, {}, {}", "Rd = Rs - Rt";
+    "AND", And { rd: Gpr @ RD, rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x0, 0x2), "{}, {}, {}", "Rd = Rs & Rt (bitwise AND)";
+    "OR", Or { rd: Gpr @ RD, rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x0, 0x3), "{}, {}, {}", "Rd = Rs | Rt (bitwise OR)";
+    "XOR", Xor { rd: Gpr @ RD, rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x0, 0x4), "{}, {}, {}", "Rd = Rs ^ Rt (bitwise XOR)";
+    "NOT", Not { rd: Gpr @ RD, rt: Gpr @ RT },
+        r(0x0, 0x5), "{}, {}", "Rd = ~Rt (bitwise NOT)";
+    "SLL", Sll { rd: Gpr @ RD, rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x0, 0x6), "{}, {}, {}", "Rd = Rs << Rt (shift left logical)";
+    "SHR", Shr { rd: Gpr @ RD, rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x0, 0x7), "{}, {}, {}", "Rd = Rs >> Rt (shift right logical)";
+    "LOADI", LoadIndirect { rd: Gpr @ RD, rs: Gpr @ RS },
+        r(0x1, 0x0), "{}, {}", "Rd = Memory[Rs] (load indirect)";
+    "STOREI", StoreIndirect { rd: Gpr @ RD, rs: Gpr @ RS },
+        r(0x1, 0x1), "{}, {}", "Memory[Rs] = Rd (store indirect)";
+    "CMP", Cmp { rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x1, 0x2), "{}, {}", "Compare Rs and Rt, set flags";
+    "RET", Return {},
+        r(0x1, 0x3), "", "Return from function (PC = Memory[SP++])";
+    "PUSH", Push { rs: Gpr @ RS },
+        r(0x1, 0x4), "{}", "Memory[--SP] = Rs";
+    "POP", Pop { rd: Gpr @ RD },
+        r(0x1, 0x5), "{}", "Rd = Memory[SP++]";
+    "ADC", AddWithCarry { rd: Gpr @ RD, rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x1, 0x6), "{}, {}, {}", "Rd = Rs + Rt + C (add with carry)";
+    "SBC", SubWithBorrow { rd: Gpr @ RD, rs: Gpr @ RS, rt: Gpr @ RT },
+        r(0x1, 0x7), "{}, {}, {}", "Rd = Rs - Rt - C (subtract with borrow)";
+
+    // I-type: [OPCODE][RT][MODE][IMMEDIATE]
+    "LOAD", Load { rt: Gpr @ RD, addr: Address @ LOW },
+        i(0x2, Some(0)), "{}, {}", "Rt = Memory[addr]";
+    "LOADO", LoadOffset { rt: Gpr @ RD, rs: Gpr @ SUB_RS, offset: BaseOffset @ LOW },
+        i(0x2, Some(1)), "{}, [{}{}]", "Rt = Memory[Rs + offset]";
+    "STORE", Store { rt: Gpr @ RD, addr: Address @ LOW },
+        i(0x3, Some(0)), "{}, {}", "Memory[addr] = Rt";
+    "STOREO", StoreOffset { rt: Gpr @ RD, rs: Gpr @ SUB_RS, offset: BaseOffset @ LOW },
+        i(0x3, Some(1)), "{}, [{}{}]", "Memory[Rs + offset] = Rt";
+    "ADDI", AddImmediate { rt: Gpr @ RD, imm: Signed @ LOW },
+        i(0x4, None), "{}, {}", "Rt = Rt + imm";
+    "ANDI", AndImmediate { rt: Gpr @ RD, imm: Unsigned @ LOW },
+        i(0x5, None), "{}, {}", "Rt = Rt & imm";
+    "ORI", OrImmediate { rt: Gpr @ RD, imm: Unsigned @ LOW },
+        i(0x6, None), "{}, {}", "Rt = Rt | imm";
+    "LUI", LoadUperImmediate { rt: Gpr @ RD, imm: Upper @ LOW },
+        i(0x7, None), "{}, {}", "Rt = imm << 8 (load upper immediate)";
+    "CMPI", CmpImmediate { rt: Gpr @ RD, imm: Signed @ LOW },
+        i(0x8, None), "{}, {}", "Compare Rt and imm, set flags";
+
+    // J-type: [OPCODE][OFFSET]
+    "CALL", Jump { offset: JumpOffset @ LOW } where jump_type = Jump::Call,
+        j(0x9), "{}", "Memory[--SP] = PC; PC = PC + offset";
+    "JMP", Jump { offset: JumpOffset @ LOW } where jump_type = Jump::Unconditional,
+        j(0xA), "{}", "PC = PC + offset";
+    "JZ", Jump { offset: JumpOffset @ LOW } where jump_type = Jump::Zero,
+        j(0xB), "{}", "if (Z) PC = PC + offset";
+    "JNZ", Jump { offset: JumpOffset @ LOW } where jump_type = Jump::NotZero,
+        j(0xC), "{}", "if (!Z) PC = PC + offset";
+    "JGT", Jump { offset: JumpOffset @ LOW } where jump_type = Jump::GreaterThan,
+        j(0xD), "{}", "if (!Z && N==V) PC = PC + offset, a > b signed after CMP a, b";
+
+    // Branches: [0xE][COND][OFFSET]
+    "JLT", Jump { offset: BranchOffset @ LOW } where jump_type = Jump::LessThan,
+        b(0x0), "{}", "if (N!=V) PC = PC + offset, a < b signed";
+    "JGE", Jump { offset: BranchOffset @ LOW } where jump_type = Jump::GreaterOrEqual,
+        b(0x1), "{}", "if (N==V) PC = PC + offset, a >= b signed";
+    "JLE", Jump { offset: BranchOffset @ LOW } where jump_type = Jump::LessOrEqual,
+        b(0x2), "{}", "if (Z || N!=V) PC = PC + offset, a <= b signed";
+    "JC", Jump { offset: BranchOffset @ LOW } where jump_type = Jump::Carry,
+        b(0x3), "{}", "if (C) PC = PC + offset, a < b unsigned (also JB)";
+    "JNC", Jump { offset: BranchOffset @ LOW } where jump_type = Jump::NotCarry,
+        b(0x4), "{}", "if (!C) PC = PC + offset, a >= b unsigned";
+    "JN", Jump { offset: BranchOffset @ LOW } where jump_type = Jump::Negative,
+        b(0x5), "{}", "if (N) PC = PC + offset";
+    "JV", Jump { offset: BranchOffset @ LOW } where jump_type = Jump::Overflow,
+        b(0x6), "{}", "if (V) PC = PC + offset";
+    "JA", Jump { offset: BranchOffset @ LOW } where jump_type = Jump::Above,
+        b(0x7), "{}", "if (!C && !Z) PC = PC + offset, a > b unsigned";
+
+    // E-type: [0xF][SUBCODE][RS][RT][FUNCT]
+    "NOP", Nop {},
+        e(0x0, None), "", "No operation";
+    "MOVS", MoveFromSpecial { rt: Gpr @ SUB_RS, spec: Special @ SUB_RT },
+        e(0x1, None), "{}, {}", "Rt = SPEC (PC=0, SP=1, FLAGS=2, USP=3)";
+    "MOVS", MoveFromToSpecial { spec: Special @ SUB_RS, rt: Gpr @ SUB_RT },
+        e(0x2, None), "{}, {}", "SPEC = Rt (PC=0, SP=1, FLAGS=2, USP=3)";
+    "RETI", ReturnFromInterrupt {},
+        e(0x3, None), "", "Return from interrupt (FLAGS = Memory[SP++]; PC = Memory[SP++])";
+    "MUL", Mul { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x4, Some(0x0)), "{}, {}", "Rd = Rd * Rs (low 16 bits)";
+    "MULH", MulHigh { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x4, Some(0x1)), "{}, {}", "Rd = (Rd * Rs) >> 16, signed";
+    "MULHU", MulHighUnsigned { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x4, Some(0x2)), "{}, {}", "Rd = (Rd * Rs) >> 16, unsigned";
+    "DIV", Div { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x5, Some(0x0)), "{}, {}", "Rd = Rd / Rs, signed, rounded toward zero";
+    "DIVU", DivUnsigned { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x5, Some(0x1)), "{}, {}", "Rd = Rd / Rs, unsigned";
+    "REM", Rem { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x5, Some(0x2)), "{}, {}", "Rd = Rd % Rs, signed, sign of Rd";
+    "REMU", RemUnsigned { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x5, Some(0x3)), "{}, {}", "Rd = Rd % Rs, unsigned";
+    "LOADB", LoadByte { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x6, Some(0x0)), "{}, {}", "Rd = byte Memory[Rs], sign extended";
+    "LOADBU", LoadByteUnsigned { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x6, Some(0x1)), "{}, {}", "Rd = byte Memory[Rs], zero extended";
+    "STOREB", StoreByte { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x6, Some(0x2)), "{}, {}", "byte Memory[Rs] = low byte of Rd";
+    "JR", JumpRegister { rs: Gpr @ SUB_RT },
+        e(0x7, Some(0x0)), "{}", "PC = Rs";
+    "CALLR", CallRegister { rs: Gpr @ SUB_RT },
+        e(0x7, Some(0x1)), "{}", "Memory[--SP] = PC; PC = Rs";
+    "CMPC", CmpWithBorrow { rs: Gpr @ SUB_RS, rt: Gpr @ SUB_RT },
+        e(0x8, Some(0x0)), "{}, {}", "Compare Rs and Rt + C, set flags";
+    "SRA", ShiftRightArithmetic { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x9, Some(0x0)), "{}, {}", "Rd = Rd >> Rs (shift right arithmetic)";
+    "ROL", RotateLeft { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x9, Some(0x1)), "{}, {}", "Rd = Rd rotated left by Rs";
+    "ROR", RotateRight { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0x9, Some(0x2)), "{}, {}", "Rd = Rd rotated right by Rs";
+    "RCL", RotateLeftCarry { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0xA, Some(0x0)), "{}, {}", "C:Rd rotated left by Rs (through carry)";
+    "RCR", RotateRightCarry { rd: Gpr @ SUB_RS, rs: Gpr @ SUB_RT },
+        e(0xA, Some(0x1)), "{}, {}", "C:Rd rotated right by Rs (through carry)";
+    "SYSCALL", Sysall {},
+        e(0xE, None), "", "System call, number in R1";
+    "HALT", Halt {},
+        e(0xF, None), "", "Stop processor execution";
+}
+
+// Fixed bits of each format as (value, mask). `None` leaves MODE or FUNCT out of the mask.
+const fn r(opcode: u16, funct: u16) -> (u16, u16) {
+    (opcode << 12 | funct, 0xF007)
+}
+
+const fn i(opcode: u16, mode: Option<u16>) -> (u16, u16) {
+    match mode {
+        Some(mode) => (opcode << 12 | mode << 8, 0xF100),
+        None => (opcode << 12, 0xF000),
+    }
+}
+
+const fn j(opcode: u16) -> (u16, u16) {
+    (opcode << 12, 0xF000)
+}
+
+const fn b(condition: u16) -> (u16, u16) {
+    (0xE000 | condition << 9, 0xFE00)
+}
+
+const fn e(subcode: u16, funct: Option<u16>) -> (u16, u16) {
+    match funct {
+        Some(funct) => (0xF000 | subcode << 8 | funct, 0xFF03),
+        None => (0xF000 | subcode << 8, 0xFF00),
+    }
+}
+
+const fn field_mask(width: u32) -> u16 {
+    (1 << width) - 1
+}
+
+// Values of a two's complement field
+pub(super) const fn signed_range(width: u32) -> RangeInclusive<i16> {
+    RangeInclusive::new(-(1 << (width - 1)), (1 << (width - 1)) - 1)
+}
+
+// Number of bits of the operand `field` of the instruction, None if it has no such operand
+pub(super) fn operand_width(instruction: &Instruction, field: &str) -> Option<u32> {
+    ENTRIES[instruction.entry()]
+        .operands
+        .iter()
+        .find(|operand| operand.field == field)
+        .map(|operand| operand.width)
+}
+
+// Row of the entry with the given fixed bits, they are unique as no two entries overlap
+const fn entry(encoding: (u16, u16)) -> usize {
+    let mut index = 0;
+    while index < ENTRIES.len() {
+        if ENTRIES[index].value == encoding.0 && ENTRIES[index].mask == encoding.1 {
+            return index;
+        }
+        index += 1;
+    }
+    panic!("no such entry");
+}
+
+pub(crate) const ENTRY_COUNT: usize = ENTRIES.len();
+
+// Rows of the instructions written with `mnemonic`, ignoring case. Empty for unknown names and
+// assembler aliases such as JB, which decode to another instruction.
+pub(crate) fn entries(mnemonic: &str) -> impl Iterator<Item = usize> + '_ {
+    ENTRIES
+        .iter()
+        .enumerate()
+        .filter(move |(_, entry)| entry.mnemonic.eq_ignore_ascii_case(mnemonic))
+        .map(|(index, _)| index)
+}
+
+pub fn is_mnemonic(name: &str) -> bool {
+    entries(name).next().is_some()
+}
+
+// How an operand is stored in its bits of the word and written in assembly
+trait Field {
+    type Value: Copy;
+    const WIDTH: u32;
+
+    // `bits` are the field shifted down to bit 0
+    fn decode(bits: u16) -> Result<Self::Value>;
+    fn encode(value: Self::Value) -> Result<u16>;
+    fn show(value: Self::Value, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    // Name of the operand in the reference, and the letter marking its bits
+    fn name(field: &'static str) -> &'static str {
+        field
+    }
+
+    fn letter(field: &'static str) -> char {
+        field.chars().next().unwrap_or('?')
+    }
+}
+
+struct Show<K: Field>(K::Value, PhantomData<K>);
+
+impl<K: Field> fmt::Display for Show<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        K::show(self.0, f)
+    }
+}
+
+// General purpose register R0-R7
+struct Gpr;
+
+impl Field for Gpr {
+    type Value = R;
+    const WIDTH: u32 = 3;
+
+    fn decode(bits: u16) -> Result<R> {
+        R::new(bits as u8)
+    }
+
+    fn encode(value: R) -> Result<u16> {
+        Ok(gpr(value)? as u16)
+    }
+
+    fn show(value: R, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", value)
+    }
+
+    fn name(field: &'static str) -> &'static str {
+        match field {
+            "rd" => "Rd",
+            "rs" => "Rs",
+            _ => "Rt",
+        }
+    }
+
+    fn letter(field: &'static str) -> char {
+        field.chars().last().unwrap_or('?')
+    }
+}
+
+// PC, SP, FLAGS or USP, numbered as in `Register::new_special`
+struct Special;
+
+impl Field for Special {
+    type Value = R;
+    const WIDTH: u32 = 3;
+
+    fn decode(bits: u16) -> Result<R> {
+        R::new_special(bits as u8)
+    }
+
+    fn encode(value: R) -> Result<u16> {
+        Ok(special(value)? as u16)
+    }
+
+    fn show(value: R, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", value)
+    }
+
+    fn name(_: &'static str) -> &'static str {
+        "SPEC"
+    }
+
+    fn letter(_: &'static str) -> char {
+        'x'
+    }
+}
+
+// 8-bit immediates: signed, unsigned, and the upper byte of LUI which reads better in hex
+struct Signed;
+struct Unsigned;
+struct Upper;
+
+impl Field for Signed {
+    type Value = i8;
+    const WIDTH: u32 = 8;
+
+    fn decode(bits: u16) -> Result<i8> {
+        Ok(bits as u8 as i8)
+    }
+
+    fn encode(value: i8) -> Result<u16> {
+        Ok(value as u8 as u16)
+    }
+
+    fn show(value: i8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", value)
+    }
+}
+
+impl Field for Unsigned {
+    type Value = u8;
+    const WIDTH: u32 = 8;
+
+    fn decode(bits: u16) -> Result<u8> {
+        Ok(bits as u8)
+    }
+
+    fn encode(value: u8) -> Result<u16> {
+        Ok(value as u16)
+    }
+
+    fn show(value: u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", value)
+    }
+}
+
+impl Field for Upper {
+    type Value = u8;
+    const WIDTH: u32 = 8;
+
+    fn decode(bits: u16) -> Result<u8> {
+        Ok(bits as u8)
+    }
+
+    fn encode(value: u8) -> Result<u16> {
+        Ok(value as u16)
+    }
+
+    fn show(value: u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02X}", value)
+    }
+}
+
+// Absolute address of LOAD and STORE, within the first 256 bytes
+struct Address;
+
+impl Field for Address {
+    type Value = u8;
+    const WIDTH: u32 = 8;
+
+    fn decode(bits: u16) -> Result<u8> {
+        Ok(bits as u8)
+    }
+
+    fn encode(value: u8) -> Result<u16> {
+        Ok(value as u16)
+    }
+
+    fn show(value: u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:04X}", value)
+    }
+}
+
+// Signed byte offset added to the base register of LOADO and STOREO, written with its sign
+struct BaseOffset;
+
+impl Field for BaseOffset {
+    type Value = i8;
+    const WIDTH: u32 = 5;
+
+    fn decode(bits: u16) -> Result<i8> {
+        let unused = 8 - Self::WIDTH;
+        Ok(((bits as u8) << unused) as i8 >> unused)
+    }
+
+    fn encode(value: i8) -> Result<u16> {
+        if !BASE_OFFSET_RANGE.contains(&value) {
+            return Err(InstructionError::OffsetOutOfRange(value as i16));
+        }
+        Ok(value as u16 & field_mask(Self::WIDTH))
+    }
+
+    fn show(value: i8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:+}", value)
+    }
+
+    fn name(_: &'static str) -> &'static str {
+        "+offset"
+    }
+}
+
+// Jump offsets relative to the next instruction. Both are kept in `Instruction::Jump` as
+// 12-bit two's complement, the 9-bit one of the branches is sign extended when decoded.
+struct JumpOffset;
+struct BranchOffset;
+
+impl Field for JumpOffset {
+    type Value = u16;
+    const WIDTH: u32 = 12;
+
+    fn decode(bits: u16) -> Result<u16> {
+        Ok(bits)
+    }
+
+    fn encode(value: u16) -> Result<u16> {
+        Ok(value & 0x0FFF)
+    }
+
+    fn show(value: u16, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", convert_12bit_to_signed(value))
+    }
+}
+
+impl Field for BranchOffset {
+    type Value = u16;
+    const WIDTH: u32 = 9;
+
+    fn decode(bits: u16) -> Result<u16> {
+        match bits >> (Self::WIDTH - 1) {
+            0 => Ok(bits),
+            _ => Ok(bits | (0x0FFF & !field_mask(Self::WIDTH))),
+        }
+    }
+
+    fn encode(value: u16) -> Result<u16> {
+        let offset = convert_12bit_to_signed(value);
+        if !signed_range(Self::WIDTH).contains(&offset) {
+            return Err(InstructionError::OffsetOutOfRange(offset));
+        }
+        Ok(value & field_mask(Self::WIDTH))
+    }
+
+    fn show(value: u16, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        JumpOffset::show(value, f)
+    }
+}
+
+struct Entry {
+    mnemonic: &'static str,
+    syntax: &'static str,
+    value: u16,
+    mask: u16,
+    operands: &'static [Operand],
+    description: &'static str,
+}
+
+struct Operand {
+    field: &'static str,
+    name: fn(&'static str) -> &'static str,
+    letter: fn(&'static str) -> char,
+    shift: u32,
+    width: u32,
+}
+
+impl Operand {
+    fn name(&self) -> &'static str {
+        (self.name)(self.field)
+    }
+}
+
+impl Entry {
+    // Assembly syntax with operand names, e.g. `LOADO Rt, [Rs+offset]`
+    fn usage(&self) -> String {
+        let mut usage = self.mnemonic.to_string();
+        let mut parts = self.syntax.split("{}");
+        if !self.operands.is_empty() {
+            usage.push(' ');
+        }
+        usage.extend(parts.next());
+        for (operand, text) in self.operands.iter().zip(parts) {
+            usage.push_str(operand.name());
+            usage.push_str(text);
+        }
+        usage
+    }
+
+    // Bits from 15 to 0: fixed bits as 0/1, operands as their letter and ignored bits as `-`,
+    // grouped by field
+    fn pattern(&self) -> String {
+        let owner = |bit: u32| {
+            if self.mask & (1 << bit) != 0 {
+                // the opcode is a group of its own
+                return Some(if bit >= 12 { 0 } else { 1 });
+            }
+            self.operands
+                .iter()
+                .position(|op| (op.shift..op.shift + op.width).contains(&bit))
+                .map(|index| index + 2)
+        };
+
+        let mut pattern = String::new();
+        for bit in (0..16).rev() {
+            if bit < 15 && owner(bit) != owner(bit + 1) {
+                pattern.push(' ');
+            }
+            pattern.push(match owner(bit) {
+                Some(0 | 1) => match self.value & (1 << bit) {
+                    0 => '0',
+                    _ => '1',
+                },
+                Some(index) => {
+                    let operand = &self.operands[index - 2];
+                    (operand.letter)(operand.field)
+                }
+                None => '-',
+            });
+        }
+        pattern
+    }
+}
+
+// The instruction set reference, docs/isa.md
+pub fn reference() -> String {
+    let rows: Vec<[String; 3]> = ENTRIES
+        .iter()
+        .map(|entry| {
+            [
+                format!("`{}`", entry.usage()),
+                format!("`{}`", entry.pattern()),
+                entry.description.replace('|', "\\|"),
+            ]
+        })
+        .collect();
+    let header = [
+        "Instruction".to_string(),
+        "Encoding".to_string(),
+        "Description".to_string(),
+    ];
+    let widths: Vec<usize> = (0..3)
+        .map(|column| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[column].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |cells: [&str; 3]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+            .collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+
+    let mut text = String::from(
+        "# SPARK-16 Instruction Reference\n\
+         \n\
+         Generated from the instruction table in `s16vm/src/cpu/instructions/isa.rs`, do not edit.\n\
+         Regenerate with `S16VM_UPDATE_ISA=1 cargo test`.\n\
+         \n\
+         Encodings list bits 15 to 0. `0` and `1` are fixed, letters mark the bits of an operand\n\
+         (`x` for SPEC) and `-` bits are ignored when decoding and encoded as 0.\n\
+         \n",
+    );
+    text.push_str(&line(header.each_ref().map(String::as_str)));
+    text.push_str(&line([&rule[0], &rule[1], &rule[2]]));
+    for row in rows.iter() {
+        text.push_str(&line(row.each_ref().map(String::as_str)));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    const REFERENCE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../docs/isa.md");
+
+    #[test]
+    fn test_entries_do_not_overlap() {
+        for bits in 0..=u16::MAX {
+            let matching: Vec<String> = ENTRIES
+                .iter()
+                .filter(|entry| bits & entry.mask == entry.value)
+                .map(Entry::usage)
+                .collect();
+            assert!(matching.len() <= 1, "0x{:04X} matches {:?}", bits, matching);
+        }
+    }
+
+    #[test]
+    fn test_entries_round_trip() {
+        for (index, entry) in ENTRIES.iter().enumerate() {
+            let operand_mask = entry
+                .operands
+                .iter()
+                .fold(0, |mask, op| mask | field_mask(op.width) << op.shift);
+            assert_eq!(operand_mask & entry.mask, 0, "{}", entry.usage());
+
+            // every combination of the operand bits, counting through the set bits of the mask
+            let mut decoded = 0;
+            let mut operands: u16 = 0;
+            loop {
+                let bits = entry.value | operands;
+                // special register fields have 3 bits for 4 registers
+                if let Ok(instruction) = Instruction::decode(Word::new(bits)) {
+                    decoded += 1;
+                    assert_eq!(instruction.mnemonic(), entry.mnemonic);
+                    assert_eq!(instruction.entry(), index);
+                    assert_eq!(
+                        instruction.encode().unwrap().to_bits(),
+                        bits,
+                        "{}",
+                        instruction
+                    );
+                    assert_eq!(
+                        assemble(&instruction.to_string(), 0).unwrap(),
+                        bits.to_le_bytes(),
+                        "{}",
+                        instruction
+                    );
+                }
+
+                if operands == operand_mask {
+                    break;
+                }
+                operands = (operands | !operand_mask).wrapping_add(1) & operand_mask;
+            }
+            assert!(decoded > 0, "{} never decodes", entry.usage());
+        }
+    }
+
+    #[test]
+    fn test_jump_offset_range() {
+        use Jump::*;
+        for jump_type in [
+            Call,
+            Unconditional,
+            Zero,
+            NotZero,
+            GreaterThan,
+            LessThan,
+            GreaterOrEqual,
+            LessOrEqual,
+            Carry,
+            NotCarry,
+            Negative,
+            Overflow,
+            Above,
+        ] {
+            let range = jump_type.offset_range();
+            for offset in [*range.start(), *range.end()] {
+                let instruction = Instruction::Jump {
+                    jump_type,
+                    offset: offset as u16 & 0x0FFF,
+                };
+                let word = instruction.encode().unwrap();
+                assert_eq!(Instruction::decode(word).unwrap(), instruction);
+            }
+            let outside = Instruction::Jump {
+                jump_type,
+                offset: (*range.end() + 1) as u16 & 0x0FFF,
+            };
+            if *range.end() < 2047 {
+                assert!(outside.encode().is_err());
+            }
+        }
+        assert_eq!(Call.offset_range(), -2048..=2047);
+        assert_eq!(Carry.offset_range(), -256..=255);
+    }
+
+    #[test]
+    fn test_reference_is_up_to_date() {
+        let reference = reference();
+        if std::env::var_os("S16VM_UPDATE_ISA").is_some() {
+            std::fs::write(REFERENCE, &reference).unwrap();
+        }
+        let current = std::fs::read_to_string(REFERENCE).unwrap_or_default();
+        assert!(
+            current == reference,
+            "docs/isa.md is out of date, regenerate it with `S16VM_UPDATE_ISA=1 cargo test`"
+        );
+    }
+}
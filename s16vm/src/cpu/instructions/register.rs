@@ -2,7 +2,8 @@ use super::error::InstructionError;
 
 type Result<T> = std::result::Result<T, InstructionError>;
 
-#[derive(Debug, Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Register {
     R0 = 0,
     R1 = 1,
@@ -17,6 +18,7 @@ pub enum Register {
     SP,
     PC,
     FLAGS,
+    USP, // user mode stack pointer, supervisor only
 }
 
 impl std::fmt::Display for Register {
@@ -25,6 +27,7 @@ impl std::fmt::Display for Register {
             Self::SP => write!(f, "SP"),
             Self::PC => write!(f, "PC"),
             Self::FLAGS => write!(f, "FLAGS"),
+            Self::USP => write!(f, "USP"),
             _ => write!(f, "R{}", self.idx())
         }
     }
@@ -33,7 +36,7 @@ impl std::fmt::Display for Register {
 impl Register {
     pub fn idx(self) -> u8 {
         match self {
-            Self::SP | Self::PC | Self::FLAGS => 0xF as u8,
+            Self::SP | Self::PC | Self::FLAGS | Self::USP => 0xF,
             _ => self as u8,
         }
     }
@@ -55,10 +58,39 @@ impl Register {
 
         Ok(reg)
     }
+
+    // Special registers are addressed by their own index in MOVS (PC=0, SP=1, FLAGS=2, USP=3)
+    pub fn new_special(id: u8) -> Result<Self> {
+        use Register::*;
+
+        let reg = match id {
+            0 => PC,
+            1 => SP,
+            2 => FLAGS,
+            3 => USP,
+            _ => return Err(InstructionError::InvalidSpecialRegister(id)),
+        };
+
+        Ok(reg)
+    }
+
+    pub fn is_special(self) -> bool {
+        matches!(self, Self::SP | Self::PC | Self::FLAGS | Self::USP)
+    }
+
+    pub fn special_idx(self) -> Option<u8> {
+        match self {
+            Self::PC => Some(0),
+            Self::SP => Some(1),
+            Self::FLAGS => Some(2),
+            Self::USP => Some(3),
+            _ => None,
+        }
+    }
 }
 
-impl Into<u8> for Register {
-    fn into(self) -> u8 {
-        self.idx()
+impl From<Register> for u8 {
+    fn from(reg: Register) -> u8 {
+        reg.idx()
     }
 }
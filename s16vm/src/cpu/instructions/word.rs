@@ -7,17 +7,20 @@ const SUB_RT_SHIFT: u8 = 2;
 const RD_SHIFT: u8 = 9;
 const RS_SHIFT: u8 = 6;
 const RT_SHIFT: u8 = 3;
+const MODE_SHIFT: u8 = 8;
 
 const OPCODE_MASK: u16 = 0b1111_0000_0000_0000;
 const RD_MASK: u16 = 0b0000_1110_0000_0000;
 const RS_MASK: u16 = 0b0000_0001_1100_0000;
 const RT_MASK: u16 = 0b0000_0000_0011_1000;
 const FUNCT_MASK: u16 = 0b0000_0000_0000_0111;
+const MODE_MASK: u16 = 0b0000_0001_0000_0000;
 const IMMEDIATE_MASK: u16 = 0b0000_0000_1111_1111;
 const OFFSET_MASK: u16 = 0b0000_1111_1111_1111;
 const SUBCODE_MASK: u16 = 0b0000_1111_0000_0000;
 const SUB_RS_MASK: u16 = 0b0000_0000_1110_0000;
 const SUB_RT_MASK: u16 = 0b0000_0000_0001_1100;
+const SUB_FUNCT_MASK: u16 = 0b0000_0000_0000_0011;
 
 // A single word can code 4 different insturction types:
 //
@@ -30,9 +33,11 @@ const SUB_RT_MASK: u16 = 0b0000_0000_0001_1100;
 // ### I-Type (Immediate Operations)
 // ```
 // 15 14 13 12 | 11 10 09 | 08 | 07 06 05 04 03 02 01 00
-// OPCODE      | RT       | 0  | IMMEDIATE
+// OPCODE      | RT       | MODE | IMMEDIATE
 // ```
 // - Immediate range: -128 to +127
+// - MODE 1 turns LOAD and STORE into base+offset addressing, IMMEDIATE is then RS (bits 7-5)
+//   and a 5-bit unsigned offset. MODE is 0 for the others
 //
 // ### J-Type (Jump Operations)
 // ```
@@ -40,12 +45,15 @@ const SUB_RT_MASK: u16 = 0b0000_0000_0001_1100;
 // OPCODE      | OFFSET (12-bit signed)
 // ```
 // - Jump range: -2048 to +2047 (relative to PC)
+// - Opcode 0xE splits the offset into a 3-bit branch condition and a 9-bit signed offset
 //
 // ### E-Type (Extended Instructions)
 // ```
 // 15 14 13 12 | 11 10 09 08 | 07 06  05 | 04 03 02 | 01 00
-// 0xF         | SUBCODE     | RS        | RT       | 0x0
+// 0xF         | SUBCODE     | RS        | RT       | FUNCT
 // ```
+// - FUNCT selects one of the instructions sharing a subcode, 0 for the others
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Word {
     RType {
@@ -58,6 +66,7 @@ pub enum Word {
     IType {
         opcode: u8,
         rt: u8,
+        mode: u8,
         imm: u8,
     },
     JType {
@@ -68,6 +77,7 @@ pub enum Word {
         subcode: u8,
         rs: u8,
         rt: u8,
+        funct: u8,
     },
 }
 
@@ -93,16 +103,18 @@ impl Word {
             0x2..=0x8 => Self::IType {
                 opcode,
                 rt: ((bits & RD_MASK) >> RD_SHIFT) as u8,
+                mode: ((bits & MODE_MASK) >> MODE_SHIFT) as u8,
                 imm: (bits & IMMEDIATE_MASK) as u8,
             },
-            0x9..=0xD => Self::JType {
+            0x9..=0xE => Self::JType {
                 opcode,
-                offset: (bits & OFFSET_MASK) as u16,
+                offset: bits & OFFSET_MASK,
             },
             0xF => Self::EType {
                 subcode: ((bits & SUBCODE_MASK) >> SUBCODE_SHIFT) as u8,
                 rs: ((bits & SUB_RS_MASK) >> SUB_RS_SHIFT) as u8,
                 rt: ((bits & SUB_RT_MASK) >> SUB_RT_SHIFT) as u8,
+                funct: (bits & SUB_FUNCT_MASK) as u8,
             },
             _ => Self::RType {
                 opcode: 0,
@@ -167,7 +179,7 @@ impl Word {
 
     pub fn funct(self) -> Option<u8> {
         match self {
-            Self::RType { funct, .. } => Some(funct),
+            Self::RType { funct, .. } | Self::EType { funct, .. } => Some(funct),
             _ => None,
         }
     }
@@ -193,22 +205,35 @@ impl Word {
             Self::IType {
                 opcode,
                 rt,
+                mode,
                 imm: immediate,
-            } => (opcode as u16) << OPCODE_SHIFT | (rt as u16) << RD_SHIFT | (immediate as u16),
+            } => {
+                (opcode as u16) << OPCODE_SHIFT
+                    | (rt as u16) << RD_SHIFT
+                    | (mode as u16) << MODE_SHIFT
+                    | (immediate as u16)
+            }
 
-            Self::JType { opcode, offset } => (opcode as u16) << OPCODE_SHIFT | (offset as u16),
+            Self::JType { opcode, offset } => (opcode as u16) << OPCODE_SHIFT | offset,
 
-            Self::EType { subcode, rs, rt } => {
+            Self::EType {
+                subcode,
+                rs,
+                rt,
+                funct,
+            } => {
                 (0xF << OPCODE_SHIFT)
                     | (subcode as u16) << SUBCODE_SHIFT
                     | (rs as u16) << SUB_RS_SHIFT
                     | (rt as u16) << SUB_RT_SHIFT
+                    | (funct as u16)
             }
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
 mod tests {
     use super::*;
 
@@ -248,11 +273,25 @@ mod tests {
                 Word::IType {
                     opcode: 0b0110,
                     rt: 0b0101,
+                    mode: 0,
                     imm: 0b011111111
                 }
             );
         }
 
+        {
+            let w = Word::new(0b0010_001_1_010_00100);
+            assert_eq!(
+                w,
+                Word::IType {
+                    opcode: 0b0010,
+                    rt: 0b001,
+                    mode: 1,
+                    imm: 0b010_00100
+                }
+            );
+        }
+
         {
             let w = Word::new(0b1010_1100_1101_1110);
             assert_eq!(
@@ -271,7 +310,21 @@ mod tests {
                 Word::EType {
                     subcode: 0b1100,
                     rs: 0b010,
-                    rt: 0b011
+                    rt: 0b011,
+                    funct: 0
+                }
+            );
+        }
+
+        {
+            let w = Word::new(0b1111_1100_010_011_10);
+            assert_eq!(
+                w,
+                Word::EType {
+                    subcode: 0b1100,
+                    rs: 0b010,
+                    rt: 0b011,
+                    funct: 0b10
                 }
             );
         }
@@ -310,6 +363,7 @@ mod tests {
             let word = Word::IType {
                 opcode: 0x6,
                 rt: 0x5,
+                mode: 0,
                 imm: 0xFF,
             };
             let bits = word.to_bits();
@@ -321,9 +375,21 @@ mod tests {
                 subcode: 0xF,
                 rs: 0x6,
                 rt: 0x7,
+                funct: 0,
             };
             let bits = word.to_bits();
             assert_eq!(bits, 0b1111_1111_1101_1100);
         }
+
+        {
+            let word = Word::EType {
+                subcode: 0xF,
+                rs: 0x6,
+                rt: 0x7,
+                funct: 0x1,
+            };
+            let bits = word.to_bits();
+            assert_eq!(bits, 0b1111_1111_1101_1101);
+        }
     }
 }
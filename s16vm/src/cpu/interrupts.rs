@@ -0,0 +1,117 @@
+use super::{cycles::INTERRUPT_ENTRY_CYCLES, error::CpuError, CPU};
+
+type Result<T> = std::result::Result<T, CpuError>;
+
+pub const INTERRUPT_LINES: u8 = 8;
+
+// Handler address for line N is the word at VECTOR_TABLE + 2 * N
+pub const VECTOR_TABLE: u16 = 0x0000;
+
+impl CPU {
+    // Marks an interrupt line as pending, it's taken before the next instruction
+    // if interrupts are enabled and the line is not masked.
+    pub fn raise_interrupt(&mut self, line: u8) {
+        self.pending_interrupts |= 1 << (line % INTERRUPT_LINES);
+    }
+
+    pub fn get_pending_interrupts(&self) -> u8 {
+        self.pending_interrupts
+    }
+
+    // Enters the handler of the highest priority (lowest numbered) pending line in supervisor
+    // mode: PC and FLAGS are pushed to the stack, interrupts are disabled until RETI
+    // restores FLAGS or the handler enables them explicitly.
+    pub(super) fn handle_interrupts(&mut self) -> Result<()> {
+        if !self.flags.interrupt_enable {
+            return Ok(());
+        }
+
+        let active = self.pending_interrupts & !self.flags.interrupt_mask;
+        if active == 0 {
+            return Ok(());
+        }
+
+        let line = active.trailing_zeros() as u16;
+        self.enter_trap(VECTOR_TABLE + 2 * line, None)?;
+        self.pending_interrupts &= !(1 << line);
+        self.flags.interrupt_enable = false;
+        self.spend_cycles(INTERRUPT_ENTRY_CYCLES);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    // Vector table at 0x0000 followed by the code starting at 0x0010
+    const PROGRAM: &str = "
+        .word 0, 0, handler2, 0, 0, handler5, 0, 0
+    start:
+        MOVS R1, FLAGS
+        ORI  R1, 0x10
+        MOVS FLAGS, R1      # enable interrupts
+    loop:
+        CMPI R3, 2
+        JNZ  loop
+        HALT
+
+    handler2:
+        ADDI R3, 1
+        ADD  R4, R0, R3     # R4 = order in which handler2 ran
+        RETI
+    handler5:
+        ADDI R3, 1
+        ADD  R5, R0, R3
+        RETI
+    ";
+
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(PROGRAM, 0).unwrap(), 0).unwrap();
+        cpu.set_pc(0x10);
+        cpu
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut cpu = cpu();
+        cpu.run_steps(10).unwrap();
+
+        cpu.raise_interrupt(5);
+        cpu.raise_interrupt(2);
+        cpu.run_steps(100).unwrap();
+
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.get_registers()[4], 1);
+        assert_eq!(cpu.get_registers()[5], 2);
+        assert_eq!(cpu.get_sp(), 0xFFFE);
+        assert_eq!(cpu.get_register(crate::Register::FLAGS) & 0x10, 0x10);
+    }
+
+    #[test]
+    fn test_disabled_and_masked() {
+        let mut cpu = cpu();
+
+        // Interrupts are not enabled yet
+        cpu.raise_interrupt(2);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x12);
+
+        cpu.run_steps(10).unwrap();
+        assert_eq!(cpu.get_registers()[4], 1);
+
+        cpu.flags.interrupt_mask = 1 << 5;
+        cpu.raise_interrupt(5);
+        cpu.run_steps(10).unwrap();
+        assert_eq!(cpu.get_registers()[5], 0);
+        assert_eq!(cpu.get_pending_interrupts(), 1 << 5);
+
+        cpu.flags.interrupt_mask = 0;
+        cpu.run_steps(10).unwrap();
+        assert_eq!(cpu.get_registers()[5], 2);
+        assert!(cpu.is_halted());
+    }
+}
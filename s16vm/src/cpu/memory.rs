@@ -1,27 +1,46 @@
-use super::error::CpuError;
+use super::bus::Device;
 
 #[derive(Debug)]
 pub enum MemoryError {
     OutOfBounds(u16),
+    Unmapped(u16),
+    ReadOnly(u16),
 }
 
-type Result<T> = std::result::Result<T, MemoryError>;
+impl std::fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryError::OutOfBounds(addr) => write!(f, "memory out of bounds addr=0x{:X}", addr),
+            MemoryError::Unmapped(addr) => write!(f, "unmapped memory addr=0x{:X}", addr),
+            MemoryError::ReadOnly(addr) => write!(f, "write to read-only memory addr=0x{:X}", addr),
+        }
+    }
+}
 
+pub type Result<T> = std::result::Result<T, MemoryError>;
+
+// Plain RAM, 64KB by default. Addresses are relative to the start of the block.
 pub struct Memory {
-    data: [u8; 0x10000], // 64KB, [0x0000,0xFFFF]
+    data: Vec<u8>,
 }
 
 impl Default for Memory {
     fn default() -> Self {
-        Self { data: [0; 0x10000] }
+        Self::new(0x10000) // 64KB, [0x0000,0xFFFF]
     }
 }
 
 impl Memory {
+    pub fn new(size: usize) -> Self {
+        Self {
+            data: vec![0; size],
+        }
+    }
+
     pub fn read_word(&self, address: u16) -> Result<u16> {
         let addr = address as usize;
 
-        if addr >= 0x10000 - 1 {
+        if addr + 1 >= self.data.len() {
             return Err(MemoryError::OutOfBounds(address));
         }
 
@@ -29,13 +48,13 @@ impl Memory {
         let low = self.data[addr] as u16;
         let high = self.data[addr + 1] as u16;
         let word = (high << 8) | low;
-        return Ok(word);
+        Ok(word)
     }
 
     pub fn write_word(&mut self, address: u16, value: u16) -> Result<()> {
         let addr = address as usize;
 
-        if addr >= 0x10000 - 1 {
+        if addr + 1 >= self.data.len() {
             return Err(MemoryError::OutOfBounds(address));
         }
 
@@ -48,30 +67,100 @@ impl Memory {
         Ok(())
     }
 
+    pub fn read_byte(&self, address: u16) -> Result<u8> {
+        self.data
+            .get(address as usize)
+            .copied()
+            .ok_or(MemoryError::OutOfBounds(address))
+    }
+
     pub fn write_byte(&mut self, address: u16, byte: u8) -> Result<()> {
         let addr = address as usize;
 
-        if addr >= 0x10000 {
+        if addr >= self.data.len() {
             return Err(MemoryError::OutOfBounds(address));
         }
 
         self.data[addr] = byte;
         Ok(())
     }
+}
+
+impl Device for Memory {
+    fn read_byte(&mut self, offset: u16) -> Result<u8> {
+        Memory::read_byte(self, offset)
+    }
+
+    fn write_byte(&mut self, offset: u16, value: u8) -> Result<()> {
+        Memory::write_byte(self, offset, value)
+    }
+
+    fn read_word(&mut self, offset: u16) -> Result<u16> {
+        Memory::read_word(self, offset)
+    }
+
+    fn write_word(&mut self, offset: u16, value: u16) -> Result<()> {
+        Memory::write_word(self, offset, value)
+    }
+
+    fn peek_byte(&self, offset: u16) -> Option<u8> {
+        Memory::read_byte(self, offset).ok()
+    }
 
-    pub fn get_range(&self, start: u16, length: u16) -> Vec<u8> {
-        let start_idx = start as usize;
-        let end = start as u32 + length as u32;
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(self.data.clone())
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> bool {
+        if state.len() != self.data.len() {
+            return false;
+        }
+        self.data.copy_from_slice(state);
+        true
+    }
+
+    fn is_memory(&self) -> bool {
+        true
+    }
+}
 
-        if end > 0xFFFF {
-            Vec::new()
-        } else {
-            let end_idx = (start + length) as usize;
-            self.data[start_idx..end_idx].to_vec()
+// Read-only memory initialized with a fixed image. Writes fail with `MemoryError::ReadOnly`.
+pub struct Rom {
+    memory: Memory,
+}
+
+impl Rom {
+    pub fn new(image: &[u8]) -> Self {
+        Self {
+            memory: Memory {
+                data: image.to_vec(),
+            },
         }
     }
 }
 
+impl Device for Rom {
+    fn read_byte(&mut self, offset: u16) -> Result<u8> {
+        self.memory.read_byte(offset)
+    }
+
+    fn write_byte(&mut self, offset: u16, _value: u8) -> Result<()> {
+        Err(MemoryError::ReadOnly(offset))
+    }
+
+    fn read_word(&mut self, offset: u16) -> Result<u16> {
+        self.memory.read_word(offset)
+    }
+
+    fn peek_byte(&self, offset: u16) -> Option<u8> {
+        self.memory.read_byte(offset).ok()
+    }
+
+    fn is_memory(&self) -> bool {
+        true
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,168 @@
+use super::{error::CpuError, Result, CPU};
+
+// FLAGS bit of the privilege mode: set in supervisor mode, cleared in user mode.
+// Each mode has its own stack pointer, SP always refers to the one of the current mode.
+pub const FLAG_SUPERVISOR: u16 = 0x20;
+
+// Handler address for SYSCALL executed in user mode is the word at SYSCALL_VECTOR
+pub const SYSCALL_VECTOR: u16 = 0x0012;
+
+impl CPU {
+    pub fn is_supervisor(&self) -> bool {
+        self.flags.supervisor
+    }
+
+    // Stack pointer of the mode that is not running, e.g. the kernel stack while in user mode
+    pub fn get_other_sp(&self) -> u16 {
+        self.other_sp
+    }
+
+    pub fn set_other_sp(&mut self, sp: u16) {
+        self.other_sp = sp;
+    }
+
+    // Writes FLAGS, switching stacks when the mode changes
+    pub(super) fn set_flags(&mut self, value: u16) {
+        let supervisor = self.flags.supervisor;
+        self.flags.set_u16(value);
+        if self.flags.supervisor != supervisor {
+            std::mem::swap(&mut self.sp, &mut self.other_sp);
+        }
+    }
+
+    pub(super) fn check_privileged(&self) -> Result<()> {
+        match self.flags.supervisor {
+            true => Ok(()),
+            false => Err(CpuError::PrivilegeViolation(self.pc.wrapping_sub(2))),
+        }
+    }
+
+    // Common entry of interrupt, exception and user mode SYSCALL handlers: switches to
+    // supervisor mode, pushes PC and FLAGS as they were to the supervisor stack followed by
+    // `cause`, and jumps to the address stored at `vector`. Nothing but memory below the
+    // stack is changed if it fails.
+    pub(super) fn enter_trap(&mut self, vector: u16, cause: Option<u16>) -> Result<()> {
+        let (pc, sp, other_sp, flags) = (self.pc, self.sp, self.other_sp, self.flags);
+
+        let entered = (|| {
+            let saved_flags = flags.as_u16();
+            self.set_flags(saved_flags | FLAG_SUPERVISOR);
+            let handler = self.read_data_word(vector)?;
+            self.push_word(pc)?;
+            self.push_word(saved_flags)?;
+            if let Some(cause) = cause {
+                self.push_word(cause)?;
+            }
+            self.pc = handler;
+            Ok(())
+        })();
+
+        if entered.is_err() {
+            (self.pc, self.sp, self.other_sp, self.flags) = (pc, sp, other_sp, flags);
+        }
+        entered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, cpu::instructions::register::Register};
+
+    // A kernel which starts a user program at `user` with its own stack and serves
+    // its system calls: number 1 adds R2 to R7, anything else is passed to the host.
+    const PROGRAM: &str = "
+        .word 0, 0, 0, 0, 0, 0, 0, 0, 0, syscall
+    kernel:
+        LUI  R1, 0x80
+        MOVS USP, R1        # user stack at 0x8000
+        MOVS R1, FLAGS
+        ANDI R1, 0xDF       # clear S
+        MOVS FLAGS, R1      # switch to user mode and its stack
+    user:
+        ADDI R1, 1
+        ADDI R2, 5
+        SYSCALL
+        ADD  R3, R0, R7
+        PUSH R3
+        MOVS SP, R0         # privileged
+    syscall:
+        CMPI R1, 1
+        JNZ  host
+        ADD  R7, R7, R2
+        RETI
+    host:
+        SYSCALL
+        RETI
+    ";
+
+    fn machine() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(PROGRAM, 0).unwrap(), 0).unwrap();
+        cpu.set_pc(0x14);
+        cpu
+    }
+
+    #[test]
+    fn test_user_mode() {
+        let mut cpu = machine();
+        cpu.run_steps(5).unwrap();
+        assert!(!cpu.is_supervisor());
+        assert_eq!(cpu.get_sp(), 0x8000);
+        assert_eq!(cpu.get_other_sp(), 0xFFFE);
+
+        // SYSCALL switched to the kernel stack, RETI returned to the user stack
+        cpu.run_steps(9).unwrap();
+        assert!(!cpu.is_supervisor());
+        assert_eq!(cpu.get_registers()[3], 5);
+        assert_eq!(cpu.get_sp(), 0x7FFE);
+        assert_eq!(cpu.get_other_sp(), 0xFFFE);
+        assert_eq!(
+            cpu.get_bus().get_range(0xFFFA, 4),
+            vec![0x00, 0x00, 0x24, 0x00]
+        );
+
+        assert!(matches!(
+            cpu.step(),
+            Err(CpuError::PrivilegeViolation(0x0028))
+        ));
+
+        // a new program doesn't inherit the mode and the user stack
+        cpu.load_program(assemble("HALT", 0x100).unwrap(), 0x100)
+            .unwrap();
+        assert!(cpu.is_supervisor());
+        assert_eq!(cpu.get_sp(), 0xFFFE);
+        assert_eq!(cpu.get_other_sp(), 0x0000);
+    }
+
+    #[test]
+    fn test_privileged_access() {
+        let mut cpu = machine();
+        cpu.get_bus_mut().map(
+            0xFF00..=0xFF01,
+            Box::new(crate::cpu::devices::console::Console::default()),
+        );
+        cpu.set_register(Register::FLAGS, 0);
+        assert_eq!(cpu.get_sp(), 0x0000);
+        cpu.set_register(Register::SP, 0x9000);
+
+        for (source, address) in [("MOVS FLAGS, R1", 0x0014), ("RETI", 0x0014)] {
+            cpu.load_program(assemble(source, 0x14).unwrap(), 0x14)
+                .unwrap();
+            cpu.set_register(Register::FLAGS, 0);
+            assert!(matches!(
+                cpu.step(),
+                Err(CpuError::PrivilegeViolation(a)) if a == address
+            ));
+        }
+
+        cpu.load_program(assemble("LUI R1, 0xFF\n LOADI R2, R1", 0x14).unwrap(), 0x14)
+            .unwrap();
+        cpu.set_register(Register::FLAGS, 0);
+        cpu.step().unwrap();
+        assert!(matches!(
+            cpu.step(),
+            Err(CpuError::PrivilegeViolation(0x0016))
+        ));
+    }
+}
@@ -0,0 +1,409 @@
+use super::{error::CpuError, Result, CPU};
+
+// Memory protection unit. While enabled, every data access and instruction fetch made by
+// the program has to fall into a region allowing it, addresses outside of all regions
+// can't be accessed at all. In user mode regions also need the USER permission. Host accesses
+// (program loading, debuggers, syscalls) are not checked. When the unit is disabled only the
+// program bounds are enforced for fetches.
+pub const REGION_COUNT: usize = 8;
+
+// The configuration can be exposed to the guest as a window of word registers:
+//   +0x00             CONTROL: bit 0 enables the unit
+//   +0x08 * (N + 1)   region N: START, END (inclusive), PERMS, reserved
+// PERMS holds Permissions bits and PERMS_VALID, regions without it are ignored.
+// The window is only accessible in supervisor mode.
+pub const WINDOW_SIZE: u16 = 0x08 * (REGION_COUNT as u16 + 1);
+pub const CONTROL_ENABLE: u16 = 0x0001;
+pub const PERMS_VALID: u16 = 0x8000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions(u8);
+
+impl Permissions {
+    pub const NONE: Self = Self(0);
+    pub const READ: Self = Self(0x1);
+    pub const WRITE: Self = Self(0x2);
+    pub const EXECUTE: Self = Self(0x4);
+    pub const USER: Self = Self(0x8);
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits & 0xF)
+    }
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    // Any combination of r, w, x and u, "-" for none
+    pub fn parse(text: &str) -> Option<Self> {
+        if text == "-" {
+            return Some(Self::NONE);
+        }
+        text.chars().try_fold(Self::NONE, |perms, c| match c {
+            'r' => Some(perms | Self::READ),
+            'w' => Some(perms | Self::WRITE),
+            'x' => Some(perms | Self::EXECUTE),
+            'u' => Some(perms | Self::USER),
+            _ => None,
+        })
+    }
+}
+
+impl std::ops::BitOr for Permissions {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl std::fmt::Display for Permissions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flag = |perm: Self, c: char| if self.contains(perm) { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}{}",
+            flag(Self::READ, 'r'),
+            flag(Self::WRITE, 'w'),
+            flag(Self::EXECUTE, 'x'),
+            flag(Self::USER, 'u')
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u16,
+    pub end: u16, // inclusive
+    pub permissions: Permissions,
+}
+
+impl Region {
+    pub fn contains(&self, address: u16) -> bool {
+        self.start <= address && address <= self.end
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Protection {
+    enabled: bool,
+    regions: [Option<Region>; REGION_COUNT],
+    window: Option<u16>, // base address of the guest configuration window
+}
+
+impl Protection {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn region(&self, index: usize) -> Option<Region> {
+        self.regions[index]
+    }
+
+    // Regions may overlap, the highest numbered one wins
+    pub fn set_region(&mut self, index: usize, region: Option<Region>) {
+        self.regions[index] = region;
+    }
+
+    pub fn window(&self) -> Option<u16> {
+        self.window
+    }
+
+    // Maps the configuration registers at `base`, they take precedence over the bus
+    pub fn set_window(&mut self, base: Option<u16>) {
+        self.window = base;
+    }
+
+    // None if no region covers the address
+    pub fn permissions(&self, address: u16) -> Option<Permissions> {
+        self.regions
+            .iter()
+            .rev()
+            .flatten()
+            .find(|region| region.contains(address))
+            .map(|region| region.permissions)
+    }
+
+    // True if every byte of the access is allowed, always true while disabled
+    pub fn allows(&self, address: u16, size: u16, needed: Permissions) -> bool {
+        !self.enabled
+            || (0..size).all(|i| {
+                self.permissions(address.wrapping_add(i))
+                    .is_some_and(|perms| perms.contains(needed))
+            })
+    }
+
+    fn window_offset(&self, address: u16) -> Option<u16> {
+        let offset = address.wrapping_sub(self.window?);
+        (offset < WINDOW_SIZE).then_some(offset)
+    }
+
+    fn read_register(&self, offset: u16) -> u16 {
+        match (offset / 8, offset % 8) {
+            (0, 0) => self.enabled as u16,
+            (0, _) => 0,
+            (n, field) => {
+                let Some(region) = self.regions.get(n as usize - 1).copied().flatten() else {
+                    return 0;
+                };
+                match field {
+                    0 => region.start,
+                    2 => region.end,
+                    4 => PERMS_VALID | region.permissions.bits() as u16,
+                    _ => 0,
+                }
+            }
+        }
+    }
+
+    // Writing START or END of an invalid region creates it without permissions
+    fn write_register(&mut self, offset: u16, value: u16) {
+        match (offset / 8, offset % 8) {
+            (0, 0) => self.enabled = value & CONTROL_ENABLE != 0,
+            (0, _) => {}
+            (n, field) => {
+                let slot = &mut self.regions[n as usize - 1];
+                let mut region = slot.unwrap_or(Region {
+                    start: 0,
+                    end: 0,
+                    permissions: Permissions::NONE,
+                });
+                match field {
+                    0 => region.start = value,
+                    2 => region.end = value,
+                    4 if value & PERMS_VALID == 0 => {
+                        *slot = None;
+                        return;
+                    }
+                    4 => region.permissions = Permissions::from_bits(value as u8),
+                    _ => return,
+                }
+                *slot = Some(region);
+            }
+        }
+    }
+}
+
+impl CPU {
+    pub fn protection(&self) -> &Protection {
+        &self.protection
+    }
+
+    pub fn protection_mut(&mut self) -> &mut Protection {
+        &mut self.protection
+    }
+
+    // Word read made by the program: goes to the configuration window or, if allowed, to the bus
+    pub(super) fn read_data_word(&mut self, address: u16) -> Result<u16> {
+        if let Some(offset) = self.protection.window_offset(address) {
+            self.check_privileged()?;
+            return Ok(self.protection.read_register(offset & !1));
+        }
+        self.check_data_access(address, 2, Permissions::READ)?;
+
+        Ok(self.bus.read_word(address)?)
+    }
+
+    pub(super) fn write_data_word(&mut self, address: u16, value: u16) -> Result<()> {
+        if let Some(offset) = self.protection.window_offset(address) {
+            self.check_privileged()?;
+            self.protection.write_register(offset & !1, value);
+            return Ok(());
+        }
+        self.check_data_access(address, 2, Permissions::WRITE)?;
+
+        Ok(self.bus.write_word(address, value)?)
+    }
+
+    // Byte accesses see the window registers as little-endian words, like the bus does
+    pub(super) fn read_data_byte(&mut self, address: u16) -> Result<u8> {
+        if let Some(offset) = self.protection.window_offset(address) {
+            self.check_privileged()?;
+            let word = self.protection.read_register(offset & !1);
+            return Ok((word >> (8 * (offset & 1))) as u8);
+        }
+        self.check_data_access(address, 1, Permissions::READ)?;
+
+        Ok(self.bus.read_byte(address)?)
+    }
+
+    pub(super) fn write_data_byte(&mut self, address: u16, value: u8) -> Result<()> {
+        if let Some(offset) = self.protection.window_offset(address) {
+            self.check_privileged()?;
+            let shift = 8 * (offset & 1);
+            let word = self.protection.read_register(offset & !1);
+            let word = (word & !(0xFF << shift)) | (value as u16) << shift;
+            self.protection.write_register(offset & !1, word);
+            return Ok(());
+        }
+        self.check_data_access(address, 1, Permissions::WRITE)?;
+
+        Ok(self.bus.write_byte(address, value)?)
+    }
+
+    pub(super) fn check_fetch(&self, address: u16) -> Result<()> {
+        match self
+            .protection
+            .allows(address, 2, self.needed(Permissions::EXECUTE))
+        {
+            true => Ok(()),
+            false => Err(CpuError::ExecuteViolation(address)),
+        }
+    }
+
+    // Memory-mapped peripherals are for the supervisor only, then the regions have to allow
+    // `access`, which is either READ or WRITE
+    fn check_data_access(&self, address: u16, size: u16, access: Permissions) -> Result<()> {
+        if !self.flags.supervisor && self.bus.is_mmio(address, size) {
+            return Err(CpuError::PrivilegeViolation(self.pc.wrapping_sub(2)));
+        }
+
+        match self.protection.allows(address, size, self.needed(access)) {
+            true => Ok(()),
+            false if access == Permissions::WRITE => Err(CpuError::WriteViolation(address)),
+            false => Err(CpuError::ReadViolation(address)),
+        }
+    }
+
+    fn needed(&self, access: Permissions) -> Permissions {
+        match self.flags.supervisor {
+            true => access,
+            false => access | Permissions::USER,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    // Code at 0x100, data at 0x40, stack at the top of memory
+    const PROGRAM: &str = "
+        LOAD  R1, 0x40
+        ADDI  R1, 1
+        STORE R1, 0x40
+        PUSH  R1
+        POP   R2
+        HALT
+    ";
+
+    fn protected(source: &str) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(source, 0x100).unwrap(), 0x100)
+            .unwrap();
+
+        let protection = cpu.protection_mut();
+        let region = |start, end, permissions| {
+            Some(Region {
+                start,
+                end,
+                permissions,
+            })
+        };
+        protection.set_region(
+            0,
+            region(0x0040, 0x00FF, Permissions::READ | Permissions::WRITE),
+        );
+        protection.set_region(
+            1,
+            region(0x0100, 0x01FF, Permissions::READ | Permissions::EXECUTE),
+        );
+        protection.set_region(
+            2,
+            region(0xFF00, 0xFFFF, Permissions::READ | Permissions::WRITE),
+        );
+        protection.set_enabled(true);
+        cpu
+    }
+
+    #[test]
+    fn test_permissions() {
+        let mut cpu = protected(PROGRAM);
+        cpu.run().unwrap();
+        assert_eq!(cpu.get_registers()[2], 1);
+
+        assert_eq!(
+            Permissions::parse("rx"),
+            Some(Permissions::READ | Permissions::EXECUTE)
+        );
+        assert_eq!(Permissions::parse("rq"), None);
+        assert_eq!(
+            (Permissions::READ | Permissions::EXECUTE).to_string(),
+            "r-x-"
+        );
+    }
+
+    #[test]
+    fn test_violations() {
+        // Write over the code
+        let mut cpu = protected("STORE R1, 0x40\n LUI R2, 0x01\n STOREI R1, R2\n HALT");
+        assert!(matches!(cpu.run(), Err(CpuError::WriteViolation(0x0100))));
+        let mut cpu = protected("LUI R2, 0x01\n ORI R2, 0x05\n STOREB R1, R2\n HALT");
+        assert!(matches!(cpu.run(), Err(CpuError::WriteViolation(0x0105))));
+
+        // Read outside of all regions
+        let mut cpu = protected("LOAD R1, 0x20\n HALT");
+        assert!(matches!(cpu.run(), Err(CpuError::ReadViolation(0x0020))));
+
+        // Jump into data
+        let mut cpu = protected("MOVS PC, R1\n HALT");
+        cpu.set_register(crate::Register::R1, 0x40);
+        cpu.step().unwrap();
+        assert!(matches!(
+            cpu.step(),
+            Err(CpuError::ExecuteViolation(0x0040))
+        ));
+
+        // Disabled unit only checks the program bounds
+        let mut cpu = protected("LOAD R1, 0x20\n HALT");
+        cpu.protection_mut().set_enabled(false);
+        cpu.run().unwrap();
+    }
+
+    #[test]
+    fn test_guest_configuration() {
+        // Region 3 is made writable by the guest, then the whole unit is turned off
+        let src = "
+            LUI   R2, 0xF0
+            LUI   R1, 0x02
+            STOREI R1, R2       # region 3 START = 0x0200
+            ADDI  R2, 2
+            LUI   R1, 0x03
+            STOREI R1, R2       # END = 0x0300
+            ADDI  R2, 2
+            LUI   R1, 0x80
+            ORI   R1, 0x03
+            STOREI R1, R2       # PERMS = valid | rw
+            LUI   R3, 0x02
+            STOREI R1, R3
+            LUI   R2, 0xF0
+            ADDI  R2, -32
+            STOREI R0, R2       # CONTROL = 0
+            LOAD  R4, 0x20
+            HALT
+        ";
+        let mut cpu = protected(src);
+        cpu.protection_mut().set_window(Some(0xEFE0));
+        cpu.run().unwrap();
+
+        assert!(!cpu.protection().is_enabled());
+        assert_eq!(
+            cpu.protection().region(3),
+            Some(Region {
+                start: 0x0200,
+                end: 0x0300,
+                permissions: Permissions::READ | Permissions::WRITE,
+            })
+        );
+        assert_eq!(cpu.get_bus().get_range(0x0200, 2), vec![0x03, 0x80]);
+    }
+}
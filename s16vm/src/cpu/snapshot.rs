@@ -0,0 +1,405 @@
+use super::{
+    protection::{Permissions, Protection, Region, REGION_COUNT},
+    CPU,
+};
+
+// Snapshot layout, all numbers little-endian:
+//   magic "S16S", version: u16
+//   CPU: R0-R7, PC, SP, FLAGS: 11 x u16, halted: u8, has exit code: u8, exit code: u16,
+//        pending interrupts: u8, cycles: u64, program start: u16, program end: u16
+//   protection: enabled: u8, has window: u8, window: u16,
+//        REGION_COUNT x (valid: u8, start: u16, end: u16, permissions: u8)
+//   stack pointer of the other privilege mode: u16
+//   device count: u16, then for every mapped device in mapping order:
+//        range start: u16, range end: u16, state length: u32 (NO_STATE if the device has none),
+//        state split into PAGE_SIZE pages, every page prefixed with a tag byte:
+//        PAGE_ZERO - the page is all zeros and its data is omitted, PAGE_RAW - data follows
+//        (the last page may be shorter)
+// Snapshots of any other version are rejected.
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"S16S";
+pub const SNAPSHOT_VERSION: u16 = 3;
+
+const PAGE_SIZE: usize = 256;
+const PAGE_ZERO: u8 = 0;
+const PAGE_RAW: u8 = 1;
+const NO_STATE: u32 = u32::MAX;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    InvalidPage(u8),
+    // The snapshot was taken with different devices mapped
+    LayoutMismatch,
+    // A device rejected its state, the range start identifies it
+    DeviceState(u16),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::BadMagic => write!(f, "not a SPARK-16 snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot version {} is not supported, expected {}",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::InvalidPage(tag) => write!(f, "invalid page tag {}", tag),
+            SnapshotError::LayoutMismatch => {
+                write!(f, "snapshot devices don't match the mapped devices")
+            }
+            SnapshotError::DeviceState(start) => {
+                write!(f, "device at 0x{:04X} rejected its state", start)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+type Result<T> = std::result::Result<T, SnapshotError>;
+
+impl CPU {
+    // Complete machine state: registers, flags, execution state, program bounds, memory
+    // protection and the state of every mapped device (RAM contents, timers). Host side configuration
+    // (syscall handler, cycle costs, tracer, console streams, ROM images) is not included.
+    pub fn save_snapshot(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+
+        for reg in self.registers.iter() {
+            out.extend_from_slice(&reg.to_le_bytes());
+        }
+        for value in [self.pc, self.sp, self.flags.as_u16()] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.push(self.halted as u8);
+        out.push(self.exit_code.is_some() as u8);
+        out.extend_from_slice(&self.exit_code.unwrap_or(0).to_le_bytes());
+        out.push(self.pending_interrupts);
+        out.extend_from_slice(&self.cycles.to_le_bytes());
+        out.extend_from_slice(&self.program_start.to_le_bytes());
+        out.extend_from_slice(&self.program_end.to_le_bytes());
+
+        out.push(self.protection.is_enabled() as u8);
+        out.push(self.protection.window().is_some() as u8);
+        out.extend_from_slice(&self.protection.window().unwrap_or(0).to_le_bytes());
+        for index in 0..REGION_COUNT {
+            let region = self.protection.region(index);
+            out.push(region.is_some() as u8);
+            let (start, end, permissions) = region
+                .map_or((0, 0, 0), |r| (r.start, r.end, r.permissions.bits()));
+            out.extend_from_slice(&start.to_le_bytes());
+            out.extend_from_slice(&end.to_le_bytes());
+            out.push(permissions);
+        }
+        out.extend_from_slice(&self.other_sp.to_le_bytes());
+
+        let layout = self.bus.layout();
+        out.extend_from_slice(&(layout.len() as u16).to_le_bytes());
+        for (range, state) in layout.iter().zip(self.bus.save_states()) {
+            out.extend_from_slice(&range.start().to_le_bytes());
+            out.extend_from_slice(&range.end().to_le_bytes());
+            match state {
+                Some(state) => {
+                    out.extend_from_slice(&(state.len() as u32).to_le_bytes());
+                    for page in state.chunks(PAGE_SIZE) {
+                        if page.iter().all(|&byte| byte == 0) {
+                            out.push(PAGE_ZERO);
+                        } else {
+                            out.push(PAGE_RAW);
+                            out.extend_from_slice(page);
+                        }
+                    }
+                }
+                None => out.extend_from_slice(&NO_STATE.to_le_bytes()),
+            }
+        }
+
+        out
+    }
+
+    // Restores a state saved by `save_snapshot` into a CPU with the same devices mapped.
+    // Nothing is changed if the snapshot is malformed or the layout differs, a device
+    // rejecting its state leaves the devices mapped before it restored.
+    pub fn restore_snapshot(&mut self, snapshot: &[u8]) -> Result<()> {
+        let mut reader = Reader {
+            data: snapshot,
+            pos: 0,
+        };
+
+        if reader.bytes(4)? != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut registers = [0; 8];
+        for reg in registers.iter_mut() {
+            *reg = reader.u16()?;
+        }
+        let (pc, sp, flags) = (reader.u16()?, reader.u16()?, reader.u16()?);
+        let halted = reader.u8()? != 0;
+        let has_exit_code = reader.u8()? != 0;
+        let exit_code = has_exit_code.then_some(reader.u16()?);
+        let pending_interrupts = reader.u8()?;
+        let cycles = reader.u64()?;
+        let (program_start, program_end) = (reader.u16()?, reader.u16()?);
+        let protection = reader.protection()?;
+        let other_sp = reader.u16()?;
+
+        let layout = self.bus.layout();
+        if reader.u16()? as usize != layout.len() {
+            return Err(SnapshotError::LayoutMismatch);
+        }
+        let mut states = Vec::with_capacity(layout.len());
+        for range in layout.iter() {
+            if (reader.u16()?, reader.u16()?) != (*range.start(), *range.end()) {
+                return Err(SnapshotError::LayoutMismatch);
+            }
+            states.push(reader.state()?);
+        }
+        if reader.pos != snapshot.len() {
+            return Err(SnapshotError::Truncated);
+        }
+
+        for (index, (range, state)) in layout.iter().zip(states).enumerate() {
+            if let Some(state) = state {
+                if !self.bus.restore_state(index, &state) {
+                    return Err(SnapshotError::DeviceState(*range.start()));
+                }
+            }
+        }
+
+        registers[0] = 0;
+        self.registers = registers;
+        self.pc = pc;
+        self.sp = sp;
+        self.other_sp = other_sp;
+        self.flags.set_u16(flags);
+        self.halted = halted;
+        self.exit_code = exit_code;
+        self.pending_interrupts = pending_interrupts;
+        self.cycles = cycles;
+        self.program_start = program_start;
+        self.program_end = program_end;
+        self.protection = protection;
+        self.history.clear();
+
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(count)
+            .ok_or(SnapshotError::Truncated)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(SnapshotError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn protection(&mut self) -> Result<Protection> {
+        let mut protection = Protection::default();
+        protection.set_enabled(self.u8()? != 0);
+        let has_window = self.u8()? != 0;
+        let window = self.u16()?;
+        protection.set_window(has_window.then_some(window));
+        for index in 0..REGION_COUNT {
+            let valid = self.u8()? != 0;
+            let region = Region {
+                start: self.u16()?,
+                end: self.u16()?,
+                permissions: Permissions::from_bits(self.u8()?),
+            };
+            protection.set_region(index, valid.then_some(region));
+        }
+        Ok(protection)
+    }
+
+    // Paged device state, see the layout above
+    fn state(&mut self) -> Result<Option<Vec<u8>>> {
+        let length = self.u32()?;
+        if length == NO_STATE {
+            return Ok(None);
+        }
+
+        let mut state = Vec::new();
+        while state.len() < length as usize {
+            let size = PAGE_SIZE.min(length as usize - state.len());
+            match self.u8()? {
+                PAGE_ZERO => state.resize(state.len() + size, 0),
+                PAGE_RAW => state.extend_from_slice(self.bytes(size)?),
+                tag => return Err(SnapshotError::InvalidPage(tag)),
+            }
+        }
+
+        Ok(Some(state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asm::assemble,
+        cpu::{
+            devices::timer::{Timer, CONTROL, CTRL_ENABLE, RELOAD},
+            instructions::register::Register,
+        },
+    };
+
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.get_bus_mut()
+            .map(0xFF00..=0xFF07, Box::new(Timer::new(0)));
+        cpu
+    }
+
+    #[test]
+    fn test_resume() {
+        let src = "
+            ADDI R1, 10
+        loop:
+            ADDI R1, -1
+            STORE R1, 0x80
+            PUSH R1
+            JNZ loop
+            HALT
+        ";
+        let program = assemble(src, 0x100).unwrap();
+
+        let mut reference = cpu();
+        reference.load_program(program.clone(), 0x100).unwrap();
+        reference.get_bus_mut().write_word(0xFF00 + RELOAD, 0x1234).unwrap();
+        reference.get_bus_mut().write_byte(0xFF00 + CONTROL, CTRL_ENABLE).unwrap();
+        reference.run().unwrap();
+
+        let mut first = cpu();
+        first.load_program(program, 0x100).unwrap();
+        first
+            .get_bus_mut()
+            .write_word(0xFF00 + RELOAD, 0x1234)
+            .unwrap();
+        first
+            .get_bus_mut()
+            .write_byte(0xFF00 + CONTROL, CTRL_ENABLE)
+            .unwrap();
+        first.run_steps(15).unwrap();
+        let snapshot = first.save_snapshot();
+
+        // only the zero page, the program and the stack aren't empty
+        assert!(snapshot.len() < 3 * 257 + 253 + 160);
+
+        let mut second = cpu();
+        second.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(second.save_snapshot(), snapshot);
+        assert_eq!(
+            second.get_bus().get_range(0xFF00, 8),
+            first.get_bus().get_range(0xFF00, 8)
+        );
+
+        second.run().unwrap();
+        assert!(second.is_halted());
+        assert_eq!(second.get_registers(), reference.get_registers());
+        assert_eq!(second.get_sp(), reference.get_sp());
+        assert_eq!(second.get_cycles(), reference.get_cycles());
+        assert_eq!(
+            second.get_bus().get_range(0xFF00, 0x100),
+            reference.get_bus().get_range(0xFF00, 0x100)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let snapshot = cpu().save_snapshot();
+        let mut target = cpu();
+
+        assert_eq!(
+            target.restore_snapshot(b"S16X\x01\x00"),
+            Err(SnapshotError::BadMagic)
+        );
+        assert_eq!(
+            target.restore_snapshot(b"S16S\x04\x00"),
+            Err(SnapshotError::UnsupportedVersion(4))
+        );
+        let mut older = snapshot.clone();
+        older[4] = 2;
+        assert_eq!(
+            target.restore_snapshot(&older),
+            Err(SnapshotError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            target.restore_snapshot(&snapshot[..snapshot.len() - 1]),
+            Err(SnapshotError::Truncated)
+        );
+        assert_eq!(
+            CPU::new().restore_snapshot(&snapshot),
+            Err(SnapshotError::LayoutMismatch)
+        );
+
+        let mut corrupted = snapshot.clone();
+        *corrupted.last_mut().unwrap() = 7;
+        assert_eq!(
+            target.restore_snapshot(&corrupted),
+            Err(SnapshotError::InvalidPage(7))
+        );
+        assert!(target.restore_snapshot(&snapshot).is_ok());
+    }
+
+    #[test]
+    fn test_protection_and_privilege() {
+        let mut first = cpu();
+        first.load_program(vec![0x00, 0xFF], 0x100).unwrap();
+        first.protection_mut().set_enabled(true);
+        first.protection_mut().set_window(Some(0xFE00));
+        first.protection_mut().set_region(
+            5,
+            Some(Region {
+                start: 0x100,
+                end: 0x1FF,
+                permissions: Permissions::READ | Permissions::EXECUTE,
+            }),
+        );
+        first.set_register(Register::FLAGS, 0); // user mode, the kernel stack is put aside
+        let snapshot = first.save_snapshot();
+
+        let mut second = cpu();
+        second.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(second.protection(), first.protection());
+        assert!(!second.is_supervisor());
+        assert_eq!(second.get_other_sp(), 0xFFFE);
+        assert_eq!(second.get_pc(), 0x100);
+    }
+}
@@ -0,0 +1,210 @@
+use std::io::{Read, Write};
+
+use super::{bus::Bus, error::CpuError};
+
+type Result<T> = std::result::Result<T, CpuError>;
+
+// Calling convention:
+//   R1       - call number, receives the result after the call
+//   R2..R7   - arguments
+pub const SYS_EXIT: u16 = 0x0; // exit(code)
+pub const SYS_PUTCHAR: u16 = 0x1; // putchar(char)
+pub const SYS_GETCHAR: u16 = 0x2; // getchar() -> char or 0xFFFF on EOF
+pub const SYS_WRITE: u16 = 0x3; // write(buffer, len) -> bytes written
+pub const SYS_READ: u16 = 0x4; // read(buffer, len) -> bytes read, 0 on EOF
+
+pub const EOF: u16 = 0xFFFF;
+
+pub enum SyscallAction {
+    Continue,
+    Exit(u16),
+}
+
+// Part of the machine state a syscall handler is allowed to touch
+pub struct SyscallContext<'a> {
+    pub(super) registers: &'a mut [u16; 8],
+    pub(super) bus: &'a mut Bus,
+}
+
+impl SyscallContext<'_> {
+    pub fn number(&self) -> u16 {
+        self.registers[1]
+    }
+
+    // idx-th argument, 0 is R2
+    pub fn arg(&self, idx: usize) -> u16 {
+        self.registers[2 + idx]
+    }
+
+    pub fn set_result(&mut self, value: u16) {
+        self.registers[1] = value;
+    }
+
+    pub fn bus(&mut self) -> &mut Bus {
+        self.bus
+    }
+}
+
+pub trait SyscallHandler {
+    fn syscall(&mut self, ctx: &mut SyscallContext) -> Result<SyscallAction>;
+}
+
+// Default handler talking to the host through a pair of streams
+pub struct HostSyscalls {
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+}
+
+impl HostSyscalls {
+    pub fn new(input: Box<dyn Read>, output: Box<dyn Write>) -> Self {
+        Self { input, output }
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        let mut byte = [0u8; 1];
+        match self.input.read(&mut byte).map_err(CpuError::HostIo)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+        self.output.write_all(bytes).map_err(CpuError::HostIo)?;
+        self.output.flush().map_err(CpuError::HostIo)
+    }
+}
+
+impl Default for HostSyscalls {
+    fn default() -> Self {
+        Self::new(Box::new(std::io::stdin()), Box::new(std::io::stdout()))
+    }
+}
+
+impl SyscallHandler for HostSyscalls {
+    fn syscall(&mut self, ctx: &mut SyscallContext) -> Result<SyscallAction> {
+        match ctx.number() {
+            SYS_EXIT => return Ok(SyscallAction::Exit(ctx.arg(0))),
+            SYS_PUTCHAR => {
+                self.write_all(&[ctx.arg(0) as u8])?;
+                ctx.set_result(0);
+            }
+            SYS_GETCHAR => {
+                let result = self.read_byte()?.map_or(EOF, |b| b as u16);
+                ctx.set_result(result);
+            }
+            SYS_WRITE => {
+                let (buffer, len) = (ctx.arg(0), ctx.arg(1));
+                let bytes = ctx.bus().get_range(buffer, len);
+                if bytes.len() != len as usize {
+                    return Err(CpuError::MemoryOutOfBounds(
+                        super::memory::MemoryError::OutOfBounds(buffer),
+                    ));
+                }
+
+                self.write_all(&bytes)?;
+                ctx.set_result(len);
+            }
+            SYS_READ => {
+                let (buffer, len) = (ctx.arg(0), ctx.arg(1));
+                let mut count = 0;
+                while count < len {
+                    let Some(byte) = self.read_byte()? else {
+                        break;
+                    };
+                    ctx.bus().write_byte(buffer.wrapping_add(count), byte)?;
+                    count += 1;
+
+                    // Line based input, like a terminal
+                    if byte == b'\n' {
+                        break;
+                    }
+                }
+                ctx.set_result(count);
+            }
+            number => return Err(CpuError::InvalidSyscall(number)),
+        }
+
+        Ok(SyscallAction::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{asm::assemble, cpu::CPU};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(src: &str, input: &'static [u8]) -> (CPU, Vec<u8>) {
+        let output = SharedBuffer::default();
+        let mut cpu = CPU::new();
+        cpu.set_syscall_handler(Box::new(HostSyscalls::new(
+            Box::new(input),
+            Box::new(output.clone()),
+        )));
+
+        cpu.load_program(assemble(src, 0x100).unwrap(), 0x100)
+            .unwrap();
+        cpu.run().unwrap();
+
+        let bytes = output.0.borrow().clone();
+        (cpu, bytes)
+    }
+
+    #[test]
+    fn test_console_io() {
+        // Registers are reset with ADD Rx, R0, R0 before loading a new value
+        let src = "
+            ADDI R1, 2          # getchar
+            SYSCALL
+            ADD  R2, R1, R0
+            ADD  R1, R0, R0
+            ADDI R1, 1          # putchar
+            SYSCALL
+
+            ADD  R1, R0, R0
+            ADDI R1, 4          # read(0x40, 8)
+            ADD  R2, R0, R0
+            ADDI R2, 0x40
+            ADDI R3, 8
+            SYSCALL
+            ADD  R3, R1, R0
+            ADD  R1, R0, R0
+            ADDI R1, 3          # write(0x40, len)
+            SYSCALL
+
+            ADD  R1, R0, R0     # exit(7)
+            ADD  R2, R0, R0
+            ADDI R2, 7
+            SYSCALL
+            HALT
+        ";
+        let (cpu, output) = run(src, b"Xhello\nworld");
+
+        assert_eq!(output, b"Xhello\n");
+        assert_eq!(cpu.exit_code(), Some(7));
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.get_pc(), 0x128);
+    }
+
+    #[test]
+    fn test_getchar_eof() {
+        let (cpu, _) = run("ADDI R1, 2\nSYSCALL\nHALT", b"");
+        assert_eq!(cpu.get_registers()[1], EOF);
+        assert_eq!(cpu.exit_code(), None);
+    }
+}
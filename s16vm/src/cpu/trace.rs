@@ -0,0 +1,295 @@
+use std::io::{self, Write};
+
+use super::{
+    bus::{Access, AccessKind},
+    instructions::{register::Register, Instruction},
+};
+
+// Registers compared before and after every traced step.
+// PC is reported separately as `next_pc`, R0 never changes.
+pub(super) const TRACED_REGISTERS: [Register; 10] = [
+    Register::R1,
+    Register::R2,
+    Register::R3,
+    Register::R4,
+    Register::R5,
+    Register::R6,
+    Register::R7,
+    Register::SP,
+    Register::FLAGS,
+    Register::USP,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub register: Register,
+    pub old: u16,
+    pub new: u16,
+}
+
+// Everything one executed instruction did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub raw: u16,
+    pub instruction: Instruction,
+    pub next_pc: u16,
+    pub cycles: u64, // total after the instruction
+    pub changes: Vec<RegisterChange>,
+    pub accesses: Vec<Access>,
+}
+
+// Receives a record after every executed instruction, see `CPU::set_tracer`.
+// Errors stop execution with `CpuError::HostIo`.
+pub trait Tracer {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()>;
+}
+
+// One line per instruction:
+// 0102: 3280  STORE R1, 0x0080      W[0080]=0005
+pub struct TextTracer<W: Write> {
+    output: W,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+}
+
+impl<W: Write> Tracer for TextTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let mut line = format!(
+            "{:04X}: {:04X}  {:<20}",
+            record.pc,
+            record.raw,
+            record.instruction.to_string()
+        );
+        for change in record.changes.iter() {
+            line.push_str(&format!(" {}={:04X}", change.register, change.new));
+        }
+        if record.next_pc != record.pc.wrapping_add(2) {
+            line.push_str(&format!(" -> {:04X}", record.next_pc));
+        }
+        for access in record.accesses.iter() {
+            let kind = match access.kind {
+                AccessKind::Read => 'R',
+                AccessKind::Write => 'W',
+            };
+            let value = match access.size {
+                1 => format!("{:02X}", access.value),
+                _ => format!("{:04X}", access.value),
+            };
+            line.push_str(&format!("  {}[{:04X}]={}", kind, access.address, value));
+        }
+
+        writeln!(self.output, "{}", line.trim_end())
+    }
+}
+
+// JSON Lines, one object per instruction:
+// {"pc":256,"raw":16901,"instruction":"ADDI R1, 5","next_pc":258,"cycles":1,
+//  "changes":[{"register":"R1","old":0,"new":5}],"accesses":[]}
+// Instruction text never needs escaping.
+pub struct JsonTracer<W: Write> {
+    output: W,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let changes: Vec<String> = record
+            .changes
+            .iter()
+            .map(|c| {
+                format!(
+                    "{{\"register\":\"{}\",\"old\":{},\"new\":{}}}",
+                    c.register, c.old, c.new
+                )
+            })
+            .collect();
+        let accesses: Vec<String> = record
+            .accesses
+            .iter()
+            .map(|a| {
+                let kind = match a.kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                };
+                format!(
+                    "{{\"kind\":\"{}\",\"address\":{},\"size\":{},\"value\":{}}}",
+                    kind, a.address, a.size, a.value
+                )
+            })
+            .collect();
+
+        writeln!(
+            self.output,
+            "{{\"pc\":{},\"raw\":{},\"instruction\":\"{}\",\"next_pc\":{},\"cycles\":{},\"changes\":[{}],\"accesses\":[{}]}}",
+            record.pc,
+            record.raw,
+            record.instruction,
+            record.next_pc,
+            record.cycles,
+            changes.join(","),
+            accesses.join(",")
+        )
+    }
+}
+
+// Compact binary trace. The stream starts with the magic "S16T" and a version byte,
+// followed by records, all numbers little-endian:
+//   pc: u16, raw: u16, next_pc: u16, cycles: u8 (spent by the instruction, saturated),
+//   change count: u8, access count: u8,
+//   changes: register: u8 (0-7 - R0-R7, 9 - SP, 10 - FLAGS, 11 - USP), new value: u16,
+//   accesses: kind and size: u8 (bit 7 set for writes, low bits - size), address: u16, value: u16
+// Old register values are not stored, they are known from the previous records.
+pub struct BinaryTracer<W: Write> {
+    output: W,
+    started: bool,
+    cycles: u64,
+}
+
+pub const BINARY_TRACE_MAGIC: &[u8; 4] = b"S16T";
+pub const BINARY_TRACE_VERSION: u8 = 1;
+
+impl<W: Write> BinaryTracer<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            started: false,
+            cycles: 0,
+        }
+    }
+}
+
+impl<W: Write> Tracer for BinaryTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(16);
+        if !self.started {
+            bytes.extend_from_slice(BINARY_TRACE_MAGIC);
+            bytes.push(BINARY_TRACE_VERSION);
+            self.started = true;
+        }
+
+        let spent = record
+            .cycles
+            .saturating_sub(self.cycles)
+            .min(u8::MAX as u64) as u8;
+        self.cycles = record.cycles;
+
+        bytes.extend_from_slice(&record.pc.to_le_bytes());
+        bytes.extend_from_slice(&record.raw.to_le_bytes());
+        bytes.extend_from_slice(&record.next_pc.to_le_bytes());
+        bytes.push(spent);
+        bytes.push(record.changes.len() as u8);
+        bytes.push(record.accesses.len().min(u8::MAX as usize) as u8);
+
+        for change in record.changes.iter() {
+            let id = match change.register {
+                Register::SP => 9,
+                Register::FLAGS => 10,
+                Register::USP => 11,
+                reg => reg.idx(),
+            };
+            bytes.push(id);
+            bytes.extend_from_slice(&change.new.to_le_bytes());
+        }
+        for access in record.accesses.iter().take(u8::MAX as usize) {
+            let write = match access.kind {
+                AccessKind::Read => 0,
+                AccessKind::Write => 0x80,
+            };
+            bytes.push(write | access.size);
+            bytes.extend_from_slice(&access.address.to_le_bytes());
+            bytes.extend_from_slice(&access.value.to_le_bytes());
+        }
+
+        self.output.write_all(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, cpu::CPU};
+    use std::{cell::RefCell, rc::Rc};
+
+    // Writer shared between the tracer and the test
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(tracer: impl FnOnce(SharedBuffer) -> Box<dyn Tracer>) -> Vec<u8> {
+        let src = "
+            ADDI R1, 5
+            STORE R1, 0x80
+            JMP skip
+            NOP
+        skip:
+            HALT
+        ";
+        let buffer = SharedBuffer::default();
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(src, 0x100).unwrap(), 0x100)
+            .unwrap();
+        cpu.set_tracer(Some(tracer(buffer.clone())));
+        cpu.run().unwrap();
+
+        let output = buffer.0.borrow().clone();
+        output
+    }
+
+    #[test]
+    fn test_text() {
+        let output = trace(|out| Box::new(TextTracer::new(out)));
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "0100: 4205  ADDI R1, 5           R1=0005");
+        assert_eq!(lines[1], "0102: 3280  STORE R1, 0x0080      W[0080]=0005");
+        assert_eq!(lines[2], "0104: A002  JMP 2                -> 0108");
+        assert_eq!(lines[3], "0108: FF00  HALT");
+    }
+
+    #[test]
+    fn test_json_and_binary() {
+        let output = trace(|out| Box::new(JsonTracer::new(out)));
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(
+            lines[1],
+            "{\"pc\":258,\"raw\":12928,\"instruction\":\"STORE R1, 0x0080\",\"next_pc\":260,\"cycles\":3,\
+             \"changes\":[],\"accesses\":[{\"kind\":\"write\",\"address\":128,\"size\":2,\"value\":5}]}"
+        );
+
+        let output = trace(|out| Box::new(BinaryTracer::new(out)));
+        assert_eq!(&output[..5], b"S16T\x01");
+        assert_eq!(
+            &output[5..17],
+            &[0x00, 0x01, 0x05, 0x42, 0x02, 0x01, 1, 1, 0, 1, 0x05, 0x00]
+        );
+        assert_eq!(
+            &output[17..31],
+            &[0x02, 0x01, 0x80, 0x32, 0x04, 0x01, 2, 0, 1, 0x82, 0x80, 0x00, 0x05, 0x00]
+        );
+        assert_eq!(output.len(), 31 + 9 + 9);
+    }
+}
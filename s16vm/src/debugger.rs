@@ -0,0 +1,436 @@
+mod condition;
+mod repl;
+
+pub use condition::Condition;
+pub use repl::run_repl;
+
+use crate::cpu::{
+    bus::{Access, AccessKind},
+    error::CpuError,
+    instructions::{word::Word, Instruction},
+    CPU,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(&self, kind: AccessKind) -> bool {
+        matches!(
+            (self, kind),
+            (WatchKind::ReadWrite, _)
+                | (WatchKind::Read, AccessKind::Read)
+                | (WatchKind::Write, AccessKind::Write)
+        )
+    }
+}
+
+impl std::fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchKind::Read => write!(f, "r"),
+            WatchKind::Write => write!(f, "w"),
+            WatchKind::ReadWrite => write!(f, "rw"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub address: u16,
+    pub condition: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub start: u16,
+    pub end: u16, // inclusive
+    pub kind: WatchKind,
+}
+
+#[derive(Debug)]
+pub enum StopReason {
+    // Requested number of instructions executed
+    Step,
+    // About to execute an instruction at a breakpoint whose condition holds
+    Breakpoint(usize),
+    // The instruction just executed accessed watched memory
+    Watchpoint { id: usize, access: Access },
+    // `finish` left the current function
+    Returned,
+    // Stepping backwards reached the oldest recorded step
+    HistoryStart,
+    Halted,
+    Error(CpuError),
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "step"),
+            StopReason::Breakpoint(id) => write!(f, "breakpoint {}", id),
+            StopReason::Watchpoint { id, access } => {
+                let kind = match access.kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                };
+                write!(
+                    f,
+                    "watchpoint {}: {} 0x{:04X} at 0x{:04X}",
+                    id, kind, access.value, access.address
+                )
+            }
+            StopReason::Returned => write!(f, "returned"),
+            StopReason::HistoryStart => write!(f, "reached the start of the recorded history"),
+            StopReason::Halted => write!(f, "halted"),
+            StopReason::Error(err) => write!(f, "error: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Step(u64),
+    Next,
+    Finish,
+    Continue,
+}
+
+// Runs a CPU under control of breakpoints and watchpoints.
+// Breakpoints stop before the instruction at their address executes, watchpoints
+// stop right after the instruction that touched the watched memory.
+// Breakpoints and watchpoints share the id space.
+// The last DEFAULT_HISTORY_LIMIT steps are recorded, so execution can also go backwards.
+pub struct Debugger {
+    cpu: CPU,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_id: usize,
+}
+
+pub const DEFAULT_HISTORY_LIMIT: usize = 10_000;
+
+impl Debugger {
+    pub fn new(mut cpu: CPU) -> Self {
+        cpu.get_bus_mut().set_access_logging(true);
+        cpu.set_history_limit(DEFAULT_HISTORY_LIMIT);
+
+        Self {
+            cpu,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16, condition: Option<Condition>) -> usize {
+        let id = self.allocate_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            address,
+            condition,
+        });
+        id
+    }
+
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, kind: WatchKind) -> usize {
+        let id = self.allocate_id();
+        self.watchpoints.push(Watchpoint {
+            id,
+            start,
+            end,
+            kind,
+        });
+        id
+    }
+
+    // Removes a breakpoint or watchpoint, returns false if there is no such id
+    pub fn delete(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|b| b.id != id);
+        self.watchpoints.retain(|w| w.id != id);
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    // Instruction at PC, None if the word there doesn't decode
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        let pc = self.cpu.get_pc();
+        match self.cpu.get_bus().get_range(pc, 2)[..] {
+            [low, high] => Instruction::decode(Word::new(u16::from_le_bytes([low, high]))).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn step(&mut self, count: u64) -> StopReason {
+        self.run_until(Mode::Step(count.max(1)))
+    }
+
+    // Steps over calls: stops once execution is back on the current call depth
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> StopReason {
+        self.run_until(Mode::Next)
+    }
+
+    // Runs until the current function returns
+    pub fn finish(&mut self) -> StopReason {
+        self.run_until(Mode::Finish)
+    }
+
+    pub fn continue_execution(&mut self) -> StopReason {
+        self.run_until(Mode::Continue)
+    }
+
+    // Undoes `count` steps. Stops early at breakpoints on the instruction stepped back to
+    // and at watchpoints on memory accessed by the undone instruction.
+    pub fn step_back(&mut self, count: u64) -> StopReason {
+        self.run_back(Some(count.max(1)))
+    }
+
+    // Goes backwards until a breakpoint or watchpoint, or the start of the history
+    pub fn reverse_continue(&mut self) -> StopReason {
+        self.run_back(None)
+    }
+
+    fn run_back(&mut self, count: Option<u64>) -> StopReason {
+        let mut steps = 0;
+
+        loop {
+            let Some(accesses) = self.cpu.step_back() else {
+                return StopReason::HistoryStart;
+            };
+            steps += 1;
+
+            if let Some((id, access)) = self.watched(&accesses) {
+                return StopReason::Watchpoint { id, access };
+            }
+            if let Some(id) = self.hit_breakpoint() {
+                return StopReason::Breakpoint(id);
+            }
+            if count.is_some_and(|count| steps >= count) {
+                return StopReason::Step;
+            }
+        }
+    }
+
+    fn allocate_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn run_until(&mut self, mode: Mode) -> StopReason {
+        let mut steps = 0;
+        let mut depth: i64 = 0;
+
+        loop {
+            if self.cpu.is_halted() {
+                return StopReason::Halted;
+            }
+
+            // A breakpoint at the resume address must not stop execution right away
+            if steps > 0 {
+                if let Some(id) = self.hit_breakpoint() {
+                    return StopReason::Breakpoint(id);
+                }
+            }
+
+            let instruction = self.current_instruction();
+            self.cpu.get_bus_mut().take_accesses();
+            if let Err(err) = self.cpu.step() {
+                return StopReason::Error(err);
+            }
+            steps += 1;
+
+            match instruction {
+                Some(Instruction::Jump {
+                    jump_type: crate::cpu::instructions::Jump::Call,
+                    ..
+                })
+                | Some(Instruction::CallRegister { .. }) => depth += 1,
+                Some(Instruction::Return) => depth -= 1,
+                _ => {}
+            }
+
+            if let Some((id, access)) = self.hit_watchpoint() {
+                return StopReason::Watchpoint { id, access };
+            }
+            if self.cpu.is_halted() {
+                return StopReason::Halted;
+            }
+
+            match mode {
+                Mode::Step(count) if steps >= count => return StopReason::Step,
+                Mode::Next if depth <= 0 => return StopReason::Step,
+                Mode::Finish if depth < 0 => return StopReason::Returned,
+                _ => {}
+            }
+        }
+    }
+
+    fn hit_breakpoint(&self) -> Option<usize> {
+        let pc = self.cpu.get_pc();
+        self.breakpoints
+            .iter()
+            .filter(|b| b.address == pc)
+            .find(|b| b.condition.as_ref().is_none_or(|c| c.eval(&self.cpu)))
+            .map(|b| b.id)
+    }
+
+    fn hit_watchpoint(&mut self) -> Option<(usize, Access)> {
+        let accesses = self.cpu.get_bus_mut().take_accesses();
+        self.watched(&accesses)
+    }
+
+    fn watched(&self, accesses: &[Access]) -> Option<(usize, Access)> {
+        accesses.iter().find_map(|access| {
+            self.watchpoints
+                .iter()
+                .find(|w| w.kind.matches(access.kind) && access.overlaps(w.start, w.end))
+                .map(|w| (w.id, *access))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn debugger(src: &str) -> Debugger {
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(src, 0x100).unwrap(), 0x100)
+            .unwrap();
+        Debugger::new(cpu)
+    }
+
+    const PROGRAM: &str = "
+            ADDI R1, 3          # 0100
+        loop:
+            CALL inc            # 0102
+            ADDI R1, -1         # 0104
+            JNZ loop            # 0106
+            HALT                # 0108
+        inc:
+            NOP                 # 010A
+            LOAD R2, 0x80       # 010C
+            ADDI R2, 1          # 010E
+            STORE R2, 0x80      # 0110
+            RET                 # 0112
+    ";
+
+    #[test]
+    fn test_breakpoints() {
+        let mut dbg = debugger(PROGRAM);
+        let id = dbg.add_breakpoint(0x104, None);
+        let cond = dbg.add_breakpoint(0x10E, Some(Condition::parse("R1 == 1").unwrap()));
+
+        assert!(matches!(dbg.continue_execution(), StopReason::Breakpoint(x) if x == id));
+        assert_eq!(dbg.cpu().get_pc(), 0x104);
+        assert!(matches!(dbg.continue_execution(), StopReason::Breakpoint(x) if x == id));
+        assert!(matches!(dbg.continue_execution(), StopReason::Breakpoint(x) if x == cond));
+        assert_eq!(dbg.cpu().get_registers()[1], 1);
+
+        assert!(dbg.delete(id));
+        assert!(!dbg.delete(id));
+        assert!(matches!(dbg.continue_execution(), StopReason::Halted));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut dbg = debugger(PROGRAM);
+        let write = dbg.add_watchpoint(0x81, 0x81, WatchKind::Write);
+
+        match dbg.continue_execution() {
+            StopReason::Watchpoint { id, access } => {
+                assert_eq!(id, write);
+                assert_eq!(access.kind, AccessKind::Write);
+                assert_eq!(access.address, 0x80);
+                assert_eq!(access.value, 1);
+            }
+            reason => panic!("unexpected stop: {}", reason),
+        }
+        assert_eq!(dbg.cpu().get_pc(), 0x112);
+
+        dbg.delete(write);
+        let read = dbg.add_watchpoint(0x80, 0x83, WatchKind::Read);
+        assert!(
+            matches!(dbg.continue_execution(), StopReason::Watchpoint { id, .. } if id == read)
+        );
+        assert_eq!(dbg.cpu().get_pc(), 0x10E);
+        assert_eq!(dbg.cpu().get_registers()[2], 1);
+    }
+
+    #[test]
+    fn test_next_and_finish() {
+        let mut dbg = debugger(PROGRAM);
+
+        assert!(matches!(dbg.step(2), StopReason::Step));
+        assert_eq!(dbg.cpu().get_pc(), 0x10A);
+        assert!(matches!(dbg.finish(), StopReason::Returned));
+        assert_eq!(dbg.cpu().get_pc(), 0x104);
+
+        dbg.step(2);
+        assert_eq!(
+            dbg.current_instruction().map(|i| i.mnemonic()),
+            Some("CALL")
+        );
+        assert!(matches!(dbg.next(), StopReason::Step));
+        assert_eq!(dbg.cpu().get_pc(), 0x104);
+        assert_eq!(dbg.cpu().get_bus().get_range(0x80, 2), vec![2, 0]);
+
+        // a breakpoint inside the callee still stops `next`
+        let id = dbg.add_breakpoint(0x110, None);
+        dbg.step(2);
+        assert!(matches!(dbg.next(), StopReason::Breakpoint(x) if x == id));
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut dbg = debugger(PROGRAM);
+        assert!(matches!(dbg.continue_execution(), StopReason::Halted));
+        assert_eq!(dbg.cpu().get_bus().get_range(0x80, 2), vec![3, 0]);
+
+        let write = dbg.add_watchpoint(0x80, 0x80, WatchKind::Write);
+        assert!(matches!(dbg.reverse_continue(), StopReason::Watchpoint { id, .. } if id == write));
+        assert_eq!(dbg.cpu().get_pc(), 0x110);
+        assert_eq!(dbg.cpu().get_bus().get_range(0x80, 2), vec![2, 0]);
+        dbg.delete(write);
+
+        let id = dbg.add_breakpoint(0x104, None);
+        assert!(matches!(dbg.reverse_continue(), StopReason::Breakpoint(x) if x == id));
+        assert_eq!(dbg.cpu().get_registers()[1], 2);
+
+        assert!(matches!(dbg.step_back(2), StopReason::Step));
+        assert_eq!(dbg.cpu().get_pc(), 0x110);
+        dbg.delete(id);
+        assert!(matches!(dbg.reverse_continue(), StopReason::HistoryStart));
+        assert_eq!(dbg.cpu().get_pc(), 0x100);
+        assert_eq!(dbg.cpu().get_bus().get_range(0x80, 2), vec![0, 0]);
+
+        // and forward again
+        assert!(matches!(dbg.continue_execution(), StopReason::Halted));
+        assert_eq!(dbg.cpu().get_bus().get_range(0x80, 2), vec![3, 0]);
+    }
+}
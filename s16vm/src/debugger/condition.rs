@@ -0,0 +1,221 @@
+use crate::{
+    asm::parse_register,
+    cpu::{instructions::register::Register, CPU},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    Register(Register),
+    Flag(u16), // mask inside FLAGS
+    Number(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Compare(Value, Op, Value),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+// Breakpoint condition over registers and flags, e.g. `R1 == 5 && Z == 1 || SP < 0x8000`.
+// Operands are registers (R0-R7, PC, SP, FLAGS, USP), flags (Z, C, N, V, I, S) and numbers.
+// Comparisons are unsigned, `&&` binds tighter than `||`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut pos = 0;
+        let expr = parse_or(&tokens, &mut pos)?;
+
+        if let Some(token) = tokens.get(pos) {
+            return Err(format!("unexpected '{}'", token));
+        }
+
+        Ok(Self {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn eval(&self, cpu: &CPU) -> bool {
+        eval(&self.expr, cpu)
+    }
+}
+
+fn eval(expr: &Expr, cpu: &CPU) -> bool {
+    match expr {
+        Expr::And(a, b) => eval(a, cpu) && eval(b, cpu),
+        Expr::Or(a, b) => eval(a, cpu) || eval(b, cpu),
+        Expr::Compare(a, op, b) => {
+            let (a, b) = (value(*a, cpu), value(*b, cpu));
+            match op {
+                Op::Eq => a == b,
+                Op::Ne => a != b,
+                Op::Lt => a < b,
+                Op::Le => a <= b,
+                Op::Gt => a > b,
+                Op::Ge => a >= b,
+            }
+        }
+    }
+}
+
+fn value(value: Value, cpu: &CPU) -> u16 {
+    match value {
+        Value::Register(reg) => cpu.get_register(reg),
+        Value::Flag(mask) => (cpu.get_register(Register::FLAGS) & mask != 0) as u16,
+        Value::Number(n) => n,
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let op = match two.as_str() {
+                "==" | "!=" | "<=" | ">=" | "&&" | "||" => two,
+                _ if c == '<' || c == '>' => c.to_string(),
+                _ => return Err(format!("unexpected character '{}'", c)),
+            };
+            i += op.len();
+            tokens.push(op);
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_or(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+    let mut expr = parse_and(tokens, pos)?;
+    while tokens.get(*pos).map(String::as_str) == Some("||") {
+        *pos += 1;
+        expr = Expr::Or(Box::new(expr), Box::new(parse_and(tokens, pos)?));
+    }
+    Ok(expr)
+}
+
+fn parse_and(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+    let mut expr = parse_compare(tokens, pos)?;
+    while tokens.get(*pos).map(String::as_str) == Some("&&") {
+        *pos += 1;
+        expr = Expr::And(Box::new(expr), Box::new(parse_compare(tokens, pos)?));
+    }
+    Ok(expr)
+}
+
+fn parse_compare(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+    let a = parse_value(tokens, pos)?;
+    let op = match tokens.get(*pos).map(String::as_str) {
+        Some("==") => Op::Eq,
+        Some("!=") => Op::Ne,
+        Some("<") => Op::Lt,
+        Some("<=") => Op::Le,
+        Some(">") => Op::Gt,
+        Some(">=") => Op::Ge,
+        Some(other) => return Err(format!("expected comparison, found '{}'", other)),
+        None => return Err("expected comparison".to_string()),
+    };
+    *pos += 1;
+    let b = parse_value(tokens, pos)?;
+
+    Ok(Expr::Compare(a, op, b))
+}
+
+fn parse_value(tokens: &[String], pos: &mut usize) -> Result<Value, String> {
+    let token = tokens.get(*pos).ok_or("expected value")?;
+    *pos += 1;
+
+    if let Some(reg) = parse_register(token) {
+        return Ok(Value::Register(reg));
+    }
+
+    let flag = match token.to_ascii_uppercase().as_str() {
+        "Z" => Some(0x01),
+        "C" => Some(0x02),
+        "N" => Some(0x04),
+        "V" => Some(0x08),
+        "I" => Some(0x10),
+        "S" => Some(0x20),
+        _ => None,
+    };
+    if let Some(mask) = flag {
+        return Ok(Value::Flag(mask));
+    }
+
+    let number = match token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => token.parse::<u16>(),
+    };
+    number
+        .map(Value::Number)
+        .map_err(|_| format!("unknown value '{}'", token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn test_eval() {
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble("ADDI R1, 5\nCMPI R1, 5\nHALT", 0).unwrap(), 0)
+            .unwrap();
+        cpu.run_steps(2).unwrap();
+
+        let eval = |src: &str| Condition::parse(src).unwrap().eval(&cpu);
+        assert!(eval("R1 == 5"));
+        assert!(eval("r1==5 && Z == 1"));
+        assert!(!eval("R1 != 5"));
+        assert!(eval("R1 > 10 || PC == 0x4"));
+        assert!(eval("R2 < R1 && SP >= 0xFFFE"));
+        assert!(!eval("N == 1 || C == 1 && Z == 1 && R1 < 5"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Condition::parse("").is_err());
+        assert!(Condition::parse("R1").is_err());
+        assert!(Condition::parse("R1 = 5").is_err());
+        assert!(Condition::parse("R1 == R9").is_err());
+        assert!(Condition::parse("R1 == 5 &&").is_err());
+        assert!(Condition::parse("R1 == 5 R2").is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use std::io::{self, BufRead, Write};
+
+use super::{Condition, Debugger, StopReason, WatchKind};
+use crate::disasm;
+
+pub const HELP: &str = "\
+Commands:
+    break <addr> [if <cond>]    b   Stop before executing <addr>, optionally only when <cond> holds
+    watch <addr>[:len] [r|w|rw] w   Stop after an access to memory (default: writes)
+    delete <id>                 d   Remove a breakpoint or watchpoint
+    info                        i   List breakpoints and watchpoints
+    step [n]                    s   Execute <n> instructions (default 1)
+    next                        n   Execute one instruction, stepping over calls
+    finish                      f   Run until the current function returns
+    continue                    c   Run until a breakpoint, watchpoint or halt
+    back [n]                    bs  Undo <n> instructions (default 1)
+    reverse                     rc  Run backwards until a breakpoint, watchpoint or the oldest recorded step
+    regs                        r   Show registers
+    mem <addr> [len]            m   Show memory (default 64 bytes)
+    disas [addr] [n]            u   Disassemble <n> instructions (default 8 from PC)
+    help                        h   Show this message
+    quit                        q   Leave the debugger
+
+Conditions compare registers (R0-R7, PC, SP, FLAGS, USP), flags (Z, C, N, V, I, S) and
+numbers with ==, !=, <, <=, >, >= and combine them with && and ||.
+An empty line repeats the previous command.";
+
+// Reads debugger commands line by line until `quit` or end of input
+pub fn run_repl(
+    debugger: &mut Debugger,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    print_location(debugger, &mut output)?;
+
+    let mut last = String::new();
+    loop {
+        write!(output, "(s16db) ")?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        let line = match line.trim() {
+            "" => last.clone(),
+            text => text.to_string(),
+        };
+        if line.is_empty() {
+            continue;
+        }
+
+        match command(debugger, &line, &mut output)? {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(msg) => writeln!(output, "error: {}", msg)?,
+        }
+        last = line;
+    }
+}
+
+// Ok(true) when the debugger should exit
+fn command(
+    debugger: &mut Debugger,
+    line: &str,
+    out: &mut impl Write,
+) -> io::Result<Result<bool, String>> {
+    let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let args: Vec<&str> = rest.split_whitespace().collect();
+
+    let reason = match name {
+        "break" | "b" => {
+            let (addr, condition) = match rest.split_once(" if ") {
+                Some((addr, cond)) => match Condition::parse(cond) {
+                    Ok(cond) => (addr, Some(cond)),
+                    Err(msg) => return Ok(Err(msg)),
+                },
+                None => (rest, None),
+            };
+            let addr = match parse_number(addr.trim()) {
+                Ok(addr) => addr,
+                Err(msg) => return Ok(Err(msg)),
+            };
+            let id = debugger.add_breakpoint(addr, condition);
+            writeln!(out, "breakpoint {} at 0x{:04X}", id, addr)?;
+            return Ok(Ok(false));
+        }
+        "watch" | "w" => {
+            let (start, end, kind) = match parse_watch(&args) {
+                Ok(watch) => watch,
+                Err(msg) => return Ok(Err(msg)),
+            };
+            let id = debugger.add_watchpoint(start, end, kind);
+            writeln!(
+                out,
+                "watchpoint {} at 0x{:04X}..0x{:04X} {}",
+                id, start, end, kind
+            )?;
+            return Ok(Ok(false));
+        }
+        "delete" | "d" => {
+            let deleted = args
+                .first()
+                .and_then(|id| id.parse().ok())
+                .is_some_and(|id| debugger.delete(id));
+            if !deleted {
+                return Ok(Err(format!("no breakpoint or watchpoint '{}'", rest)));
+            }
+            return Ok(Ok(false));
+        }
+        "info" | "i" => {
+            for b in debugger.breakpoints() {
+                match &b.condition {
+                    Some(cond) => writeln!(
+                        out,
+                        "{:<3} breakpoint 0x{:04X} if {}",
+                        b.id, b.address, cond
+                    )?,
+                    None => writeln!(out, "{:<3} breakpoint 0x{:04X}", b.id, b.address)?,
+                }
+            }
+            for w in debugger.watchpoints() {
+                writeln!(
+                    out,
+                    "{:<3} watchpoint 0x{:04X}..0x{:04X} {}",
+                    w.id, w.start, w.end, w.kind
+                )?;
+            }
+            return Ok(Ok(false));
+        }
+        "step" | "s" => match args.first().map(|n| n.parse::<u64>()) {
+            None => debugger.step(1),
+            Some(Ok(count)) => debugger.step(count),
+            Some(Err(_)) => return Ok(Err(format!("invalid count '{}'", rest))),
+        },
+        "next" | "n" => debugger.next(),
+        "finish" | "f" => debugger.finish(),
+        "continue" | "c" => debugger.continue_execution(),
+        "back" | "bs" => match args.first().map(|n| n.parse::<u64>()) {
+            None => debugger.step_back(1),
+            Some(Ok(count)) => debugger.step_back(count),
+            Some(Err(_)) => return Ok(Err(format!("invalid count '{}'", rest))),
+        },
+        "reverse" | "rc" => debugger.reverse_continue(),
+        "regs" | "r" => {
+            let cpu = debugger.cpu();
+            writeln!(out, "{}", cpu.dump_registers())?;
+            writeln!(out, "CYCLES  | {}", cpu.get_cycles())?;
+            return Ok(Ok(false));
+        }
+        "mem" | "m" => {
+            let range = match args[..] {
+                [addr] => parse_number(addr).map(|addr| (addr, 64)),
+                [addr, len] => parse_number(addr).and_then(|addr| Ok((addr, parse_number(len)?))),
+                _ => Err("expected mem <addr> [len]".to_string()),
+            };
+            match range {
+                Ok((addr, len)) => writeln!(
+                    out,
+                    "{}",
+                    debugger.cpu().dump_memory_hex(addr, len).trim_end()
+                )?,
+                Err(msg) => return Ok(Err(msg)),
+            }
+            return Ok(Ok(false));
+        }
+        "disas" | "u" => {
+            let pc = debugger.cpu().get_pc();
+            let range = match args[..] {
+                [] => Ok((pc, 8)),
+                [addr] => parse_number(addr).map(|addr| (addr, 8)),
+                [addr, count] => {
+                    parse_number(addr).and_then(|addr| Ok((addr, parse_number(count)?)))
+                }
+                _ => Err("expected disas [addr] [n]".to_string()),
+            };
+            match range {
+                Ok((addr, count)) => write!(
+                    out,
+                    "{}",
+                    disasm::disassemble_memory(
+                        debugger.cpu().get_bus(),
+                        addr,
+                        count.saturating_mul(2)
+                    )
+                )?,
+                Err(msg) => return Ok(Err(msg)),
+            }
+            return Ok(Ok(false));
+        }
+        "help" | "h" => {
+            writeln!(out, "{}", HELP)?;
+            return Ok(Ok(false));
+        }
+        "quit" | "q" => return Ok(Ok(true)),
+        other => return Ok(Err(format!("unknown command '{}', try 'help'", other))),
+    };
+
+    match reason {
+        StopReason::Step => {}
+        StopReason::Halted => match debugger.cpu().exit_code() {
+            Some(code) => writeln!(out, "halted, exit code {}", code)?,
+            None => writeln!(out, "halted")?,
+        },
+        reason => writeln!(out, "{}", reason)?,
+    }
+    print_location(debugger, out)?;
+
+    Ok(Ok(false))
+}
+
+fn print_location(debugger: &Debugger, out: &mut impl Write) -> io::Result<()> {
+    let pc = debugger.cpu().get_pc();
+    let listing = disasm::disassemble_memory(debugger.cpu().get_bus(), pc, 2);
+    match listing.lines.first() {
+        Some(line) => writeln!(out, "=> {:04X}: {}", pc, line.item),
+        None => writeln!(out, "=> {:04X}", pc),
+    }
+}
+
+// <addr>[:len] [r|w|rw]
+fn parse_watch(args: &[&str]) -> Result<(u16, u16, WatchKind), String> {
+    let (range, kind) = match args {
+        [range] => (*range, WatchKind::Write),
+        [range, "r"] => (*range, WatchKind::Read),
+        [range, "w"] => (*range, WatchKind::Write),
+        [range, "rw"] => (*range, WatchKind::ReadWrite),
+        _ => return Err("expected watch <addr>[:len] [r|w|rw]".to_string()),
+    };
+
+    let (start, length) = match range.split_once(':') {
+        Some((start, length)) => (parse_number(start)?, parse_number(length)?),
+        None => (parse_number(range)?, 1),
+    };
+    if length == 0 {
+        return Err("watched range is empty".to_string());
+    }
+
+    Ok((start, start.saturating_add(length - 1), kind))
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse::<u16>(),
+    };
+
+    parsed.map_err(|_| format!("invalid number '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, cpu::CPU};
+
+    #[test]
+    fn test_session() {
+        let src = "
+            ADDI R1, 2
+        loop:
+            ADDI R1, -1
+            STORE R1, 0x20
+            JNZ loop
+            HALT
+        ";
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(src, 0x100).unwrap(), 0x100)
+            .unwrap();
+        let mut debugger = Debugger::new(cpu);
+
+        let script = "b 0x104 if R1 == 0\nwatch 0x20:2\ninfo\nc\n\nd 2\nfrobnicate\nm 0x20 2\nc\nbs 2\nrc\n";
+        let mut output = Vec::new();
+        run_repl(&mut debugger, script.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("=> 0100: ADDI R1, 2\n"));
+        assert!(output.contains("breakpoint 1 at 0x0104"));
+        assert!(
+            output.contains("1   breakpoint 0x0104 if R1 == 0\n2   watchpoint 0x0020..0x0021 w\n")
+        );
+        assert!(output.contains("watchpoint 2: write 0x0001 at 0x0020\n=> 0106: JNZ -6"));
+        // the empty line repeats `c`, the breakpoint condition holds on the second pass
+        assert!(output.contains("breakpoint 1\n=> 0104: STORE R1, 0x0020"));
+        assert!(output.contains("error: unknown command 'frobnicate'"));
+        assert!(output.contains("0x0020: 0x01 0x00"));
+        assert!(output.contains("halted\n=> 010A"));
+        assert!(output.ends_with("=> 0106: JNZ -6\n(s16db) breakpoint 1\n=> 0104: STORE R1, 0x0020\n(s16db) "));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeSet;
+
+use crate::cpu::{
+    instructions::{word::Word, Instruction},
+    bus::Bus,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Item {
+    Instruction(Instruction),
+    // A word that doesn't decode, or decodes into an instruction with a different encoding
+    // (e.g. non-zero unused bits), so it can only be represented as raw data.
+    Word(u16),
+    // Trailing byte of an odd-sized input
+    Byte(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Line {
+    pub address: u16,
+    pub raw: u16,
+    pub item: Item,
+    // Absolute jump target for J-type instructions
+    pub target: Option<u16>,
+}
+
+// Disassembled code. `Display` renders it as assembler source, which assembled
+// at the same origin reproduces the original bytes exactly.
+// Jump targets inside the listing get synthetic labels `L_XXXX`.
+pub struct Listing {
+    pub lines: Vec<Line>,
+    labels: BTreeSet<u16>,
+}
+
+impl Listing {
+    pub fn label(&self, address: u16) -> Option<String> {
+        self.labels
+            .contains(&address)
+            .then(|| format!("L_{:04X}", address))
+    }
+
+    // Text of a single line, jump targets replaced by labels when possible
+    pub fn text(&self, line: &Line) -> String {
+        match line.item {
+            Item::Instruction(Instruction::Jump { jump_type, .. }) => {
+                match line.target.and_then(|t| self.label(t)) {
+                    Some(label) => format!("{} {}", jump_type.mnemonic(), label),
+                    None => line.item.to_string(),
+                }
+            }
+            _ => line.item.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Item::Instruction(instruction) => write!(f, "{}", instruction),
+            Item::Word(word) => write!(f, ".word 0x{:04X}", word),
+            Item::Byte(byte) => write!(f, ".byte 0x{:02X}", byte),
+        }
+    }
+}
+
+impl std::fmt::Display for Listing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in self.lines.iter() {
+            if let Some(label) = self.label(line.address) {
+                writeln!(f, "{}:", label)?;
+            }
+
+            let text = format!("    {:<24}", self.text(line));
+            match (line.item, line.target) {
+                (Item::Byte(_), _) => {
+                    writeln!(f, "{}# {:04X}: {:02X}", text, line.address, line.raw)?
+                }
+                (_, Some(target)) => writeln!(
+                    f,
+                    "{}# {:04X}: {:04X} -> {:04X}",
+                    text, line.address, line.raw, target
+                )?,
+                _ => writeln!(f, "{}# {:04X}: {:04X}", text, line.address, line.raw)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn disassemble(bytes: &[u8], origin: u16) -> Listing {
+    let mut lines = Vec::with_capacity(bytes.len() / 2 + 1);
+
+    for (i, chunk) in bytes.chunks(2).enumerate() {
+        let address = origin.wrapping_add(2 * i as u16);
+
+        let line = match *chunk {
+            [low, high] => {
+                let raw = u16::from_le_bytes([low, high]);
+                let item = decode(raw);
+                let target = match item {
+                    // PC points to the next instruction when the jump executes
+                    Item::Instruction(Instruction::Jump { offset, .. }) => {
+                        Some(address.wrapping_add(2).wrapping_add_signed(
+                            crate::cpu::types::convert_12bit_to_signed(offset),
+                        ))
+                    }
+                    _ => None,
+                };
+                Line {
+                    address,
+                    raw,
+                    item,
+                    target,
+                }
+            }
+            [byte] => Line {
+                address,
+                raw: byte as u16,
+                item: Item::Byte(byte),
+                target: None,
+            },
+            _ => unreachable!(),
+        };
+        lines.push(line);
+    }
+
+    // Only targets which fall onto the start of a listed line can get a label
+    let end = origin as u32 + bytes.len() as u32;
+    let labels = lines
+        .iter()
+        .filter_map(|line| line.target)
+        .filter(|&t| t >= origin && (t as u32) < end && (t - origin).is_multiple_of(2))
+        .collect();
+
+    Listing { lines, labels }
+}
+
+pub fn disassemble_memory(bus: &Bus, start: u16, length: u16) -> Listing {
+    disassemble(&bus.get_range(start, length), start)
+}
+
+fn decode(raw: u16) -> Item {
+    let word = Word::new(raw);
+    match Instruction::decode(word) {
+        Ok(instruction) if instruction.encode().map(Word::to_bits).ok() == Some(raw) => {
+            Item::Instruction(instruction)
+        }
+        _ => Item::Word(raw),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn test_listing() {
+        let src = "
+            ADDI R1, 3
+        loop:
+            ADDI R1, -1
+            JNZ loop
+            CALL 0x100
+            MOVS R2, FLAGS
+            HALT
+            .word 0xFD00
+            .byte 0x42
+        ";
+        let bytes = assemble(src, 0x10).unwrap();
+        let listing = disassemble(&bytes, 0x10);
+        let text = listing.to_string();
+
+        assert_eq!(listing.lines[2].target, Some(0x12));
+        assert_eq!(listing.lines[3].target, Some(0x118));
+        assert_eq!(listing.lines[5].item, Item::Instruction(Instruction::Halt));
+        assert_eq!(listing.lines[6].item, Item::Word(0xFD00));
+        assert_eq!(listing.lines[7].item, Item::Byte(0x42));
+
+        assert!(text.contains("L_0012:\n    ADDI R1, -1"));
+        assert!(text.contains("    JNZ L_0012"));
+        assert!(text.contains("    CALL 256"));
+        assert!(text.contains("    MOVS R2, FLAGS"));
+
+        assert_eq!(assemble(&text, 0x10).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_round_trip_all_words() {
+        let words: Vec<u16> = (0..=u16::MAX).collect();
+
+        for chunk in words.chunks(0x1000) {
+            let bytes: Vec<u8> = chunk.iter().flat_map(|w| w.to_le_bytes()).collect();
+            let text = disassemble(&bytes, 0x100).to_string();
+            assert_eq!(assemble(&text, 0x100).unwrap(), bytes);
+        }
+    }
+}
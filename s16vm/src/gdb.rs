@@ -0,0 +1,560 @@
+use std::{
+    io::{self, Read, Write},
+    net::TcpListener,
+};
+
+use crate::{
+    cpu::{error::CpuError, instructions::register::Register, CPU},
+    debugger::{Debugger, StopReason, WatchKind},
+};
+
+pub const TARGET_XML: &str = include_str!("gdb/target.xml");
+
+// Register layout of `g`/`G` packets and register numbers of `p`/`P`,
+// every register is 16 bits, transferred little-endian
+const REGISTERS: [Register; 12] = [
+    Register::R0,
+    Register::R1,
+    Register::R2,
+    Register::R3,
+    Register::R4,
+    Register::R5,
+    Register::R6,
+    Register::R7,
+    Register::PC,
+    Register::SP,
+    Register::FLAGS,
+    Register::USP,
+];
+
+// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGFPE: u8 = 8;
+const SIGSEGV: u8 = 11;
+
+// Waits for a single debugger connection on localhost and serves it
+pub fn listen_tcp(debugger: &mut Debugger, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+
+    serve(debugger, stream)
+}
+
+#[cfg(unix)]
+pub fn listen_unix(debugger: &mut Debugger, path: &std::path::Path) -> io::Result<()> {
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    let result = listener
+        .accept()
+        .and_then(|(stream, _)| serve(debugger, stream));
+    let _ = std::fs::remove_file(path);
+
+    result
+}
+
+// Talks GDB Remote Serial Protocol over the stream until the debugger
+// detaches, kills the target or closes the connection.
+// Execution is synchronous, so `continue` can't be interrupted with Ctrl-C.
+pub fn serve(debugger: &mut Debugger, stream: impl Read + Write) -> io::Result<()> {
+    let mut connection = Connection {
+        stream,
+        last: Vec::new(),
+    };
+
+    while let Some(packet) = connection.receive()? {
+        match handle(debugger, &packet) {
+            Action::Reply(reply) => connection.send(&reply)?,
+            Action::Exit(reply) => {
+                if let Some(reply) = reply {
+                    connection.send(&reply)?;
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Connection<S> {
+    stream: S,
+    last: Vec<u8>, // retransmitted when the debugger doesn't acknowledge it
+}
+
+impl<S: Read + Write> Connection<S> {
+    // Next packet payload, None on end of stream. Acknowledges received packets.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+
+            match byte {
+                b'-' => self.stream.write_all(&self.last)?,
+                0x03 => return Ok(Some("\x03".to_string())),
+                b'$' => {
+                    let mut data = Vec::new();
+                    loop {
+                        match self.read_byte()? {
+                            Some(b'#') => break,
+                            Some(byte) => data.push(byte),
+                            None => return Ok(None),
+                        }
+                    }
+
+                    let mut checksum = [0; 2];
+                    self.stream.read_exact(&mut checksum)?;
+                    let expected = std::str::from_utf8(&checksum)
+                        .ok()
+                        .and_then(|text| u8::from_str_radix(text, 16).ok());
+
+                    if expected == Some(checksum_of(&data)) {
+                        self.stream.write_all(b"+")?;
+                        return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+                    }
+                    self.stream.write_all(b"-")?;
+                }
+                // acks and noise between packets
+                _ => {}
+            }
+        }
+    }
+
+    fn send(&mut self, payload: &str) -> io::Result<()> {
+        self.last = format!("${}#{:02x}", payload, checksum_of(payload.as_bytes())).into_bytes();
+        self.stream.write_all(&self.last)?;
+        self.stream.flush()
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+enum Action {
+    Reply(String),
+    // Ends the session, optionally replying first
+    Exit(Option<String>),
+}
+
+fn handle(debugger: &mut Debugger, packet: &str) -> Action {
+    let (command, args) = match packet.char_indices().nth(1) {
+        Some((i, _)) => packet.split_at(i),
+        None => (packet, ""),
+    };
+
+    let reply = match command {
+        "\x03" => format!("S{:02x}", SIGINT),
+        "?" => format!("S{:02x}", SIGTRAP),
+        "g" => REGISTERS
+            .iter()
+            .map(|&reg| hex_word(debugger.cpu().get_register(reg)))
+            .collect(),
+        "G" => write_registers(debugger.cpu_mut(), args),
+        "p" => match parse_hex(args).and_then(|n| REGISTERS.get(n as usize)) {
+            Some(&reg) => hex_word(debugger.cpu().get_register(reg)),
+            None => "E01".to_string(),
+        },
+        "P" => {
+            let parsed = args.split_once('=').and_then(|(n, value)| {
+                let reg = *REGISTERS.get(parse_hex(n)? as usize)?;
+                Some((reg, parse_word(value)?))
+            });
+            match parsed {
+                Some((reg, value)) => {
+                    debugger.cpu_mut().set_register(reg, value);
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            }
+        }
+        "m" => match parse_pair(args) {
+            Some((addr, len)) => {
+                // Clamped to the end of memory, reading all of it is 0x10000 bytes
+                let bus = debugger.cpu().get_bus();
+                (addr..(addr + len).min(0x10000))
+                    .map(|addr| format!("{:02x}", bus.peek_byte(addr as u16).unwrap_or(0)))
+                    .collect()
+            }
+            None => "E01".to_string(),
+        },
+        "M" => write_memory(debugger.cpu_mut(), args),
+        "Z" | "z" => match parse_point(args) {
+            Some((kind, addr, len)) => update_point(debugger, command == "Z", kind, addr, len),
+            None => "E01".to_string(),
+        },
+        "s" | "c" => {
+            if let Some(addr) = parse_hex(args) {
+                debugger.cpu_mut().set_pc(addr as u16);
+            }
+            let reason = match command {
+                "s" => debugger.step(1),
+                _ => debugger.continue_execution(),
+            };
+            stop_reply(debugger, reason)
+        }
+        "b" => match args {
+            "s" => {
+                let reason = debugger.step_back(1);
+                stop_reply(debugger, reason)
+            }
+            "c" => {
+                let reason = debugger.reverse_continue();
+                stop_reply(debugger, reason)
+            }
+            _ => String::new(),
+        },
+        "H" | "T" => "OK".to_string(),
+        "k" => return Action::Exit(None),
+        "D" => return Action::Exit(Some("OK".to_string())),
+        "q" => query(args),
+        // everything else, including vCont and binary X writes, is unsupported
+        _ => String::new(),
+    };
+
+    Action::Reply(reply)
+}
+
+fn query(query: &str) -> String {
+    if query.starts_with("Supported") {
+        return "PacketSize=1000;qXfer:features:read+;ReverseStep+;ReverseContinue+".to_string();
+    }
+    if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+        return match parse_pair(range) {
+            Some((offset, length)) => {
+                let xml = TARGET_XML.as_bytes();
+                let start = offset.min(xml.len() as u32) as usize;
+                let end = (start + length as usize).min(xml.len());
+                let marker = if end == xml.len() { 'l' } else { 'm' };
+                format!("{}{}", marker, String::from_utf8_lossy(&xml[start..end]))
+            }
+            None => "E01".to_string(),
+        };
+    }
+
+    match query {
+        "Attached" => "1",
+        "C" => "QC1",
+        "fThreadInfo" => "m1",
+        "sThreadInfo" => "l",
+        "Offsets" => "Text=0;Data=0;Bss=0",
+        _ => "",
+    }
+    .to_string()
+}
+
+fn stop_reply(debugger: &Debugger, reason: StopReason) -> String {
+    match reason {
+        StopReason::Step | StopReason::Returned | StopReason::Breakpoint(_) => {
+            format!("S{:02x}", SIGTRAP)
+        }
+        StopReason::Watchpoint { id, access } => {
+            let Some(watch) = debugger.watchpoints().iter().find(|w| w.id == id) else {
+                return format!("S{:02x}", SIGTRAP);
+            };
+            let name = match watch.kind {
+                WatchKind::Write => "watch",
+                WatchKind::Read => "rwatch",
+                WatchKind::ReadWrite => "awatch",
+            };
+            // the access may start before the watched range
+            let addr = access.address.max(watch.start);
+            format!("T{:02x}{}:{:x};", SIGTRAP, name, addr)
+        }
+        StopReason::HistoryStart => format!("T{:02x}replaylog:begin;", SIGTRAP),
+        StopReason::Halted => {
+            let code = debugger.cpu().exit_code().unwrap_or(0);
+            format!("W{:02x}", code as u8)
+        }
+        StopReason::Error(CpuError::InvalidInstruction(..)) => format!("S{:02x}", SIGILL),
+        StopReason::Error(CpuError::DivideByZero(_)) => format!("S{:02x}", SIGFPE),
+        StopReason::Error(_) => format!("S{:02x}", SIGSEGV),
+    }
+}
+
+fn write_registers(cpu: &mut CPU, data: &str) -> String {
+    if data.len() != REGISTERS.len() * 4 {
+        return "E01".to_string();
+    }
+
+    let Some(bytes) = parse_bytes(data) else {
+        return "E01".to_string();
+    };
+    for (&reg, value) in REGISTERS.iter().zip(bytes.chunks(2)) {
+        cpu.set_register(reg, u16::from_le_bytes([value[0], value[1]]));
+    }
+
+    "OK".to_string()
+}
+
+// M<addr>,<len>:<hex bytes>
+fn write_memory(cpu: &mut CPU, args: &str) -> String {
+    let Some((range, data)) = args.split_once(':') else {
+        return "E01".to_string();
+    };
+    let Some((addr, len)) = parse_pair(range) else {
+        return "E01".to_string();
+    };
+    if data.len() != 2 * len as usize || addr + len > 0x10000 {
+        return "E01".to_string();
+    }
+    let Some(bytes) = parse_bytes(data) else {
+        return "E01".to_string();
+    };
+
+    for (i, byte) in bytes.into_iter().enumerate() {
+        if cpu
+            .get_bus_mut()
+            .write_byte(addr as u16 + i as u16, byte)
+            .is_err()
+        {
+            return "E02".to_string();
+        }
+    }
+
+    "OK".to_string()
+}
+
+// Z<type>,<addr>,<kind>: 0 - breakpoint, 2 - write, 3 - read, 4 - access watchpoint
+fn parse_point(args: &str) -> Option<(u8, u16, u16)> {
+    let mut parts = args.split(',');
+    let kind = parts.next()?.parse().ok()?;
+    let addr = u16::try_from(parse_hex(parts.next()?)?).ok()?;
+    let len = u16::try_from(parse_hex(parts.next()?.split(';').next()?)?).ok()?;
+
+    Some((kind, addr, len))
+}
+
+fn update_point(debugger: &mut Debugger, insert: bool, kind: u8, addr: u16, len: u16) -> String {
+    let watch = match kind {
+        0 => None,
+        2 => Some(WatchKind::Write),
+        3 => Some(WatchKind::Read),
+        4 => Some(WatchKind::ReadWrite),
+        _ => return String::new(),
+    };
+    let end = addr.saturating_add(len.max(1) - 1);
+
+    match (watch, insert) {
+        (None, true) => {
+            debugger.add_breakpoint(addr, None);
+        }
+        (Some(watch), true) => {
+            debugger.add_watchpoint(addr, end, watch);
+        }
+        (None, false) => {
+            let ids: Vec<usize> = debugger
+                .breakpoints()
+                .iter()
+                .filter(|b| b.address == addr)
+                .map(|b| b.id)
+                .collect();
+            ids.into_iter().for_each(|id| {
+                debugger.delete(id);
+            });
+        }
+        (Some(watch), false) => {
+            let ids: Vec<usize> = debugger
+                .watchpoints()
+                .iter()
+                .filter(|w| w.start == addr && w.end == end && w.kind == watch)
+                .map(|w| w.id)
+                .collect();
+            ids.into_iter().for_each(|id| {
+                debugger.delete(id);
+            });
+        }
+    }
+
+    "OK".to_string()
+}
+
+fn hex_word(value: u16) -> String {
+    let [low, high] = value.to_le_bytes();
+    format!("{:02x}{:02x}", low, high)
+}
+
+// Little-endian 16-bit register value
+fn parse_word(text: &str) -> Option<u16> {
+    if text.len() != 4 {
+        return None;
+    }
+    let low = u8::from_str_radix(text.get(0..2)?, 16).ok()?;
+    let high = u8::from_str_radix(text.get(2..4)?, 16).ok()?;
+    Some(u16::from_le_bytes([low, high]))
+}
+
+// Pairs of hex digits, None for anything else including non-ASCII characters
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some((hex_digit(*high)? << 4) | hex_digit(*low)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok().filter(|&n| n <= 0xFFFF)
+}
+
+// <addr>,<len> in hex, addr must fit into the address space
+fn parse_pair(text: &str) -> Option<(u32, u32)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((parse_hex(addr)?, u32::from_str_radix(len, 16).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    // Scripted client: reads the given packets, collects everything the server sends
+    struct Client {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Client {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Client {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(payload: &str) -> String {
+        format!("${}#{:02x}", payload, checksum_of(payload.as_bytes()))
+    }
+
+    // Runs a session and returns replies to the packets in order
+    fn session(src: &str, packets: &[&str]) -> Vec<String> {
+        let mut cpu = CPU::new();
+        cpu.load_program(assemble(src, 0x100).unwrap(), 0x100)
+            .unwrap();
+        let mut debugger = Debugger::new(cpu);
+
+        let input: String = packets.iter().map(|p| format!("{}+", packet(p))).collect();
+        let mut client = Client {
+            input: io::Cursor::new(input.into_bytes()),
+            output: Vec::new(),
+        };
+        serve(&mut debugger, &mut client).unwrap();
+
+        let output = String::from_utf8(client.output).unwrap();
+        output
+            .split('$')
+            .skip(1)
+            .map(|reply| reply.split_once('#').unwrap().0.to_string())
+            .collect()
+    }
+
+    const PROGRAM: &str = "
+            ADDI R1, 5          # 0100
+            STORE R1, 0x80      # 0102
+            ADDI R1, -1         # 0104
+            JNZ -6              # 0106
+            HALT                # 0108
+    ";
+
+    #[test]
+    fn test_registers_and_memory() {
+        let replies = session(
+            PROGRAM,
+            &[
+                "?",
+                "g",
+                "P1=3412",
+                "p1",
+                "p8",
+                "pc",
+                "M80,3:aabbcc",
+                "M80,2:a\u{fffd}",
+                "m7f,5",
+                "m0,10000",
+                "Gffff010002000300040005000600070004010080ff1f3412",
+                "G\u{fffd}f010002000300040005000600070004010080ff1f3412",
+                "g",
+                "qXfer:features:read:target.xml:0,10",
+                "qSupported:multiprocess+",
+                "vMustReplyEmpty",
+                "D",
+            ],
+        );
+
+        assert_eq!(replies[0], "S05");
+        assert_eq!(replies[1], format!("{}0001feff20000000", "0".repeat(32)));
+        assert_eq!(replies[2], "OK");
+        assert_eq!(replies[3], "3412");
+        assert_eq!(replies[4], "0001");
+        assert_eq!(replies[5], "E01");
+        assert_eq!(replies[6], "OK");
+        // a digit cut in half by a non-ASCII character
+        assert_eq!(replies[7], "E01");
+        assert_eq!(replies[8], "00aabbcc00");
+        assert_eq!(replies[9].len(), 2 * 0x10000);
+        assert_eq!(&replies[9][2 * 0x80..2 * 0x83], "aabbcc");
+        assert_eq!(replies[10], "OK");
+        assert_eq!(replies[11], "E01");
+        // R0 is hardwired to zero, reserved FLAGS bits are dropped
+        assert_eq!(
+            replies[12],
+            "00000100020003000400050006000700040100803f1f3412"
+        );
+        assert_eq!(replies[13], "m<?xml version=\"1");
+        assert_eq!(
+            replies[14],
+            "PacketSize=1000;qXfer:features:read+;ReverseStep+;ReverseContinue+"
+        );
+        assert_eq!(replies[15], "");
+        assert_eq!(replies[16], "OK");
+    }
+
+    #[test]
+    fn test_execution() {
+        let replies = session(
+            PROGRAM,
+            &[
+                "s", "Z0,104,2", "c", "p1", "z0,104,2", "Z2,81,1", "c", "p1", "z2,81,1", "Z3,80,2",
+                "c", "z3,80,2", "c", "bs", "p8", "Z0,106,2", "bc", "p1", "z0,106,2", "bc", "p8",
+            ],
+        );
+
+        assert_eq!(replies[0], "S05");
+        assert_eq!(replies[2], "S05");
+        assert_eq!(replies[3], "0500");
+        assert_eq!(replies[6], "T05watch:81;");
+        assert_eq!(replies[7], "0400");
+        // nothing reads the variable
+        assert_eq!(replies[10], "W00");
+        assert_eq!(replies[13], "S05");
+        assert_eq!(replies[14], "0801");
+        assert_eq!(replies[16], "S05");
+        assert_eq!(replies[17], "0000");
+        assert_eq!(replies[19], "T05replaylog:begin;");
+        assert_eq!(replies[20], "0001");
+    }
+}
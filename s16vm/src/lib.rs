@@ -0,0 +1,29 @@
+// SPARK-16 emulator library: the CPU with its bus and devices, assembler,
+// disassembler and debuggers. The `s16vm` binary is a command line front end on top of it.
+//
+// The types most programs need are re-exported at the crate root:
+//
+//   let program = s16vm::asm::assemble("ADDI R1, 2\nHALT", 0x100).unwrap();
+//   let mut cpu = s16vm::CPU::new();
+//   cpu.load_program(program, 0x100).unwrap();
+//   cpu.run().unwrap();
+//   assert_eq!(cpu.get_register(s16vm::Register::R1), 2);
+
+pub mod asm;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gdb;
+// Exports for the JavaScript API, the module built for wasm32 is this crate as a cdylib
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+pub use asm::{AsmError, AsmErrorKind};
+pub use cpu::{
+    bus::{Bus, Device},
+    error::CpuError,
+    instructions::{error::InstructionError, register::Register, word::Word, Instruction, Jump},
+    memory::{Memory, MemoryError},
+    Flags, CPU,
+};
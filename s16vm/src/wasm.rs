@@ -0,0 +1,290 @@
+// C ABI used by the JavaScript wrapper in `web/s16vm.js` on wasm32-unknown-unknown.
+// There is a single machine per module instance. Byte buffers are passed through the
+// module memory: `s16_alloc` a buffer, fill it from JS, pass its pointer and length.
+//
+// The embedder provides these imports in the `env` module:
+//   s16_write(ptr, len)                 - guest console output
+//   s16_read(ptr, cap) -> len           - guest console input, 0 on EOF
+//   s16_syscall(n, a0, a1, a2) -> i32   - syscalls the VM doesn't know,
+//                                         result in R1 or -1 if unsupported
+//
+// Functions returning i32 report failures as -1, `s16_error` then describes the error.
+// `s16_step` returns a count instead, its error is cleared first and set if it failed.
+use std::{
+    cell::RefCell,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    asm,
+    cpu::{
+        devices::console::Console,
+        error::CpuError,
+        instructions::register::Register,
+        syscall::{HostSyscalls, SyscallAction, SyscallContext, SyscallHandler},
+        CPU,
+    },
+};
+
+extern "C" {
+    fn s16_write(ptr: *const u8, len: usize);
+    fn s16_read(ptr: *mut u8, cap: usize) -> usize;
+    fn s16_syscall(number: u32, a0: u32, a1: u32, a2: u32) -> i32;
+}
+
+// Register numbers of `s16_register`, same as the GDB stub
+const REGISTERS: [Register; 12] = [
+    Register::R0,
+    Register::R1,
+    Register::R2,
+    Register::R3,
+    Register::R4,
+    Register::R5,
+    Register::R6,
+    Register::R7,
+    Register::PC,
+    Register::SP,
+    Register::FLAGS,
+    Register::USP,
+];
+
+thread_local! {
+    static MACHINE: RefCell<CPU> = RefCell::new(machine());
+    static ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+struct JsInput;
+
+impl Read for JsInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(unsafe { s16_read(buf.as_mut_ptr(), buf.len()) })
+    }
+}
+
+struct JsOutput;
+
+impl Write for JsOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        unsafe { s16_write(buf.as_ptr(), buf.len()) };
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Console syscalls go to the JS streams, unknown ones to `s16_syscall`
+struct JsSyscalls {
+    host: HostSyscalls,
+}
+
+impl SyscallHandler for JsSyscalls {
+    fn syscall(&mut self, ctx: &mut SyscallContext) -> Result<SyscallAction, CpuError> {
+        match self.host.syscall(ctx) {
+            Err(CpuError::InvalidSyscall(number)) => {
+                let (a0, a1, a2) = (ctx.arg(0) as u32, ctx.arg(1) as u32, ctx.arg(2) as u32);
+                match unsafe { s16_syscall(number as u32, a0, a1, a2) } {
+                    result if result < 0 => Err(CpuError::InvalidSyscall(number)),
+                    result => {
+                        ctx.set_result(result as u16);
+                        Ok(SyscallAction::Continue)
+                    }
+                }
+            }
+            result => result,
+        }
+    }
+}
+
+fn machine() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.set_syscall_handler(Box::new(JsSyscalls {
+        host: HostSyscalls::new(Box::new(JsInput), Box::new(JsOutput)),
+    }));
+    cpu
+}
+
+fn fail(message: String) -> i32 {
+    ERROR.with(|error| *error.borrow_mut() = message);
+    -1
+}
+
+fn with_machine<T>(f: impl FnOnce(&mut CPU) -> T) -> T {
+    MACHINE.with(|cpu| f(&mut cpu.borrow_mut()))
+}
+
+unsafe fn slice<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        return &[];
+    }
+    std::slice::from_raw_parts(ptr, len)
+}
+
+#[no_mangle]
+pub extern "C" fn s16_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// # Safety
+/// `ptr` and `len` must come from `s16_alloc`
+#[no_mangle]
+pub unsafe extern "C" fn s16_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+// Pointer and length of the last error message, UTF-8
+#[no_mangle]
+pub extern "C" fn s16_error_ptr() -> *const u8 {
+    ERROR.with(|error| error.borrow().as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn s16_error_len() -> usize {
+    ERROR.with(|error| error.borrow().len())
+}
+
+// Starts over with a fresh machine: empty memory, no devices besides RAM
+#[no_mangle]
+pub extern "C" fn s16_reset() {
+    with_machine(|cpu| *cpu = machine());
+}
+
+// Maps a console device (data, status bytes) talking to the JS streams
+#[no_mangle]
+pub extern "C" fn s16_map_console(addr: u32) {
+    let addr = addr as u16;
+    with_machine(|cpu| {
+        cpu.get_bus_mut().map(
+            addr..=addr.saturating_add(1),
+            Box::new(Console::new(Box::new(JsInput), Box::new(JsOutput))),
+        )
+    });
+}
+
+/// # Safety
+/// `ptr` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn s16_load_program(ptr: *const u8, len: usize, addr: u32) -> i32 {
+    let program = slice(ptr, len).to_vec();
+    match with_machine(|cpu| cpu.load_program(program, addr as u16)) {
+        Ok(()) => 0,
+        Err(err) => fail(err.to_string()),
+    }
+}
+
+/// # Safety
+/// `ptr` must point to `len` bytes of UTF-8 assembler source
+#[no_mangle]
+pub unsafe extern "C" fn s16_load_source(ptr: *const u8, len: usize, addr: u32) -> i32 {
+    let Ok(source) = std::str::from_utf8(slice(ptr, len)) else {
+        return fail("source is not valid UTF-8".to_string());
+    };
+    let program = match asm::assemble(source, addr as u16) {
+        Ok(program) => program,
+        Err(err) => return fail(err.to_string()),
+    };
+    match with_machine(|cpu| cpu.load_program(program, addr as u16)) {
+        Ok(()) => 0,
+        Err(err) => fail(err.to_string()),
+    }
+}
+
+// Executes at most `count` instructions, returns how many were executed before it halted,
+// finished or an instruction failed
+#[no_mangle]
+pub extern "C" fn s16_step(count: u32) -> u32 {
+    ERROR.with(|error| error.borrow_mut().clear());
+    let mut steps = 0;
+    let result = with_machine(|cpu| {
+        while steps < count && cpu.step()? {
+            steps += 1;
+        }
+        Ok::<_, CpuError>(())
+    });
+    if let Err(err) = result {
+        fail(err.to_string());
+    }
+    steps
+}
+
+// Runs until the program halts, 0 on success
+#[no_mangle]
+pub extern "C" fn s16_run() -> i32 {
+    match with_machine(|cpu| cpu.run()) {
+        Ok(()) => 0,
+        Err(err) => fail(err.to_string()),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn s16_halted() -> u32 {
+    with_machine(|cpu| cpu.is_halted()) as u32
+}
+
+// Exit syscall code, -1 if the program didn't exit that way
+#[no_mangle]
+pub extern "C" fn s16_exit_code() -> i32 {
+    with_machine(|cpu| cpu.exit_code()).map_or(-1, |code| code as i32)
+}
+
+#[no_mangle]
+pub extern "C" fn s16_cycles() -> f64 {
+    with_machine(|cpu| cpu.get_cycles()) as f64
+}
+
+// 0-7 - R0-R7, 8 - PC, 9 - SP, 10 - FLAGS, 11 - USP (the user stack pointer, SP in user mode)
+#[no_mangle]
+pub extern "C" fn s16_register(index: u32) -> i32 {
+    match REGISTERS.get(index as usize) {
+        Some(&reg) => with_machine(|cpu| cpu.get_register(reg)) as i32,
+        None => fail(format!("no register {}", index)),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn s16_set_register(index: u32, value: u32) -> i32 {
+    match REGISTERS.get(index as usize) {
+        Some(&reg) => {
+            with_machine(|cpu| cpu.set_register(reg, value as u16));
+            0
+        }
+        None => fail(format!("no register {}", index)),
+    }
+}
+
+// Copies memory into `out` without side effects, returns the number of bytes copied.
+// The range is clamped to the end of memory, reading all of it is 0x10000 bytes.
+/// # Safety
+/// `out` must point to `len` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn s16_read_memory(addr: u32, len: u32, out: *mut u8) -> u32 {
+    let (start, end) = (addr.min(0x10000), addr.saturating_add(len).min(0x10000));
+    let bytes: Vec<u8> = with_machine(|cpu| {
+        (start..end)
+            .map(|addr| cpu.get_bus().peek_byte(addr as u16).unwrap_or(0))
+            .collect()
+    });
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len());
+    bytes.len() as u32
+}
+
+/// # Safety
+/// `ptr` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn s16_write_memory(addr: u32, ptr: *const u8, len: usize) -> i32 {
+    let bytes = slice(ptr, len);
+    let result = with_machine(|cpu| {
+        bytes.iter().enumerate().try_for_each(|(i, &byte)| {
+            cpu.get_bus_mut()
+                .write_byte((addr as u16).wrapping_add(i as u16), byte)
+        })
+    });
+    match result {
+        Ok(()) => 0,
+        Err(err) => fail(err.to_string()),
+    }
+}
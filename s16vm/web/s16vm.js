@@ -0,0 +1,166 @@
+// JavaScript API of the SPARK-16 VM, a thin wrapper over the exports of src/wasm.rs.
+// Works in browsers and in Node:
+//
+//   const vm = await Spark16.load(await fetch("s16vm.wasm"), { onOutput: (text) => ... });
+//   vm.loadSource("ADDI R1, 1\nHALT", 0x100);
+//   vm.runUntilHalt();
+//   console.log(vm.registers());
+//
+// Options:
+//   onOutput(text, bytes)         - console output of the guest (SYSCALL and the console device)
+//   onInput(maxBytes)             - console input, returns a string, bytes or null on EOF
+//   onSyscall(number, a0, a1, a2) - syscalls the VM doesn't handle, returns the result for R1,
+//                                   or undefined/-1 to fail with an invalid syscall error
+export class Spark16 {
+  static async load(wasm, options = {}) {
+    let vm;
+    const imports = {
+      env: {
+        s16_write: (ptr, len) => vm.output(ptr, len),
+        s16_read: (ptr, cap) => vm.input(ptr, cap),
+        s16_syscall: (n, a0, a1, a2) => {
+          const result = options.onSyscall?.(n, a0, a1, a2);
+          return result === undefined ? -1 : result;
+        },
+      },
+    };
+
+    let instance;
+    if (wasm instanceof WebAssembly.Module) {
+      instance = await WebAssembly.instantiate(wasm, imports);
+    } else if (typeof Response !== "undefined" && wasm instanceof Response) {
+      ({ instance } = await WebAssembly.instantiate(await wasm.arrayBuffer(), imports));
+    } else {
+      ({ instance } = await WebAssembly.instantiate(wasm, imports));
+    }
+
+    vm = new Spark16(instance.exports, options);
+    return vm;
+  }
+
+  constructor(exports, options) {
+    this.exports = exports;
+    this.options = options;
+    this.decoder = new TextDecoder();
+    this.encoder = new TextEncoder();
+  }
+
+  // Fresh machine with empty memory
+  reset() {
+    this.exports.s16_reset();
+  }
+
+  // Console device at addr (data byte, status byte), talking to onOutput/onInput
+  mapConsole(addr) {
+    this.exports.s16_map_console(addr);
+  }
+
+  loadProgram(bytes, addr = 0) {
+    this.withBuffer(bytes, (ptr, len) => this.check(this.exports.s16_load_program(ptr, len, addr)));
+  }
+
+  // Assembles the source at addr and loads it there
+  loadSource(source, addr = 0) {
+    const bytes = this.encoder.encode(source);
+    this.withBuffer(bytes, (ptr, len) => this.check(this.exports.s16_load_source(ptr, len, addr)));
+  }
+
+  // Executes at most count instructions, returns the number executed. If an instruction
+  // fails the error has the number executed before it in `steps`.
+  step(count = 1) {
+    const steps = this.exports.s16_step(count) >>> 0;
+    if (this.exports.s16_error_len() !== 0) {
+      throw Object.assign(this.error(), { steps });
+    }
+    return steps;
+  }
+
+  runUntilHalt() {
+    this.check(this.exports.s16_run());
+  }
+
+  get halted() {
+    return this.exports.s16_halted() !== 0;
+  }
+
+  // Code passed to the exit syscall, null if the program didn't exit that way
+  get exitCode() {
+    const code = this.exports.s16_exit_code();
+    return code < 0 ? null : code;
+  }
+
+  get cycles() {
+    return this.exports.s16_cycles();
+  }
+
+  // 0-7 - R0-R7, 8 - PC, 9 - SP, 10 - FLAGS, 11 - USP (the user stack pointer, SP in user mode)
+  register(index) {
+    return this.check(this.exports.s16_register(index));
+  }
+
+  setRegister(index, value) {
+    this.check(this.exports.s16_set_register(index, value));
+  }
+
+  registers() {
+    const values = Array.from({ length: 12 }, (_, i) => this.register(i));
+    return { r: values.slice(0, 8), pc: values[8], sp: values[9], flags: values[10], usp: values[11] };
+  }
+
+  // Copy of a memory range, reading has no side effects on devices
+  readMemory(addr, len) {
+    const ptr = this.exports.s16_alloc(len);
+    try {
+      const copied = this.exports.s16_read_memory(addr, len, ptr);
+      return new Uint8Array(this.exports.memory.buffer, ptr, copied).slice();
+    } finally {
+      this.exports.s16_free(ptr, len);
+    }
+  }
+
+  writeMemory(addr, bytes) {
+    this.withBuffer(bytes, (ptr, len) => this.check(this.exports.s16_write_memory(addr, ptr, len)));
+  }
+
+  withBuffer(bytes, f) {
+    const len = bytes.length;
+    const ptr = this.exports.s16_alloc(len);
+    try {
+      new Uint8Array(this.exports.memory.buffer, ptr, len).set(bytes);
+      return f(ptr, len);
+    } finally {
+      this.exports.s16_free(ptr, len);
+    }
+  }
+
+  check(result) {
+    if (result < 0) {
+      throw this.error();
+    }
+    return result;
+  }
+
+  error() {
+    const ptr = this.exports.s16_error_ptr();
+    const len = this.exports.s16_error_len();
+    return new Error(this.decoder.decode(new Uint8Array(this.exports.memory.buffer, ptr, len)));
+  }
+
+  output(ptr, len) {
+    const bytes = new Uint8Array(this.exports.memory.buffer, ptr, len).slice();
+    this.options.onOutput?.(this.decoder.decode(bytes), bytes);
+  }
+
+  input(ptr, cap) {
+    let data = this.options.onInput?.(cap);
+    if (data === null || data === undefined) {
+      return 0;
+    }
+    if (typeof data === "string") {
+      data = this.encoder.encode(data);
+    }
+    const len = Math.min(cap, data.length);
+    new Uint8Array(this.exports.memory.buffer, ptr, len).set(data.subarray(0, len));
+    return len;
+  }
+}
@@ -0,0 +1,82 @@
+// Headless test of the wasm build:
+//   cargo build --lib --release --target wasm32-unknown-unknown && node web/test.mjs
+import assert from "node:assert/strict";
+import { readFile } from "node:fs/promises";
+import { Spark16 } from "./s16vm.js";
+
+const path =
+  process.argv[2] ??
+  new URL("../../target/wasm32-unknown-unknown/release/s16vm.wasm", import.meta.url);
+
+let output = "";
+let input = ["A"];
+const vm = await Spark16.load(await readFile(path), {
+  onOutput: (text) => (output += text),
+  onInput: () => input.shift() ?? null,
+  onSyscall: (n, a0) => (n === 0x20 ? a0 * 2 : undefined),
+});
+
+vm.loadSource(
+  `
+    ADDI R1, 1      # putchar('H')
+    ADDI R2, 72
+    SYSCALL
+    ADD R1, R0, R0
+    ADDI R1, 2      # getchar
+    SYSCALL
+    ADD R2, R1, R0
+    ADD R1, R0, R0
+    ADDI R1, 1      # putchar(getchar())
+    SYSCALL
+    ADD R1, R0, R0
+    ADDI R1, 32     # custom syscall handled by JS
+    ADD R2, R0, R0
+    ADDI R2, 21
+    SYSCALL
+    STORE R1, 0x40
+    ADD R2, R1, R0
+    ADD R1, R0, R0  # exit(R2)
+    SYSCALL
+`,
+  0x100,
+);
+
+assert.equal(vm.step(3), 3);
+assert.equal(output, "H");
+assert.equal(vm.registers().pc, 0x106);
+
+vm.runUntilHalt();
+assert.equal(output, "HA");
+assert.ok(vm.halted);
+assert.equal(vm.exitCode, 42);
+assert.deepEqual(Array.from(vm.readMemory(0x40, 2)), [42, 0]);
+assert.equal(vm.registers().r[2], 42);
+assert.ok(vm.cycles > 0);
+
+// unknown syscalls the callback doesn't handle are errors
+vm.loadSource("ADDI R1, 33\nSYSCALL", 0);
+assert.throws(() => vm.runUntilHalt(), /syscall/);
+vm.loadSource("ADDI R1, 33\nSYSCALL", 0);
+assert.throws(() => vm.step(5), (err) => /syscall/.test(err.message) && err.steps === 1);
+
+// counts beyond i32 don't turn into errors
+vm.loadSource("ADDI R1, 1\nHALT", 0);
+assert.equal(vm.step(0x80000000), 2);
+
+assert.throws(() => vm.loadSource("FROB R1", 0), /1:1/);
+
+vm.reset();
+vm.mapConsole(0xff00);
+vm.loadProgram([0x00, 0xff], 0x10); // data, not run
+vm.writeMemory(0x20, [1, 2, 3]);
+assert.deepEqual(Array.from(vm.readMemory(0x10, 2)), [0x00, 0xff]);
+assert.deepEqual(Array.from(vm.readMemory(0x20, 3)), [1, 2, 3]);
+assert.equal(vm.readMemory(0, 0x10000).length, 0x10000);
+assert.deepEqual(Array.from(vm.readMemory(0xfffe, 4)), [0, 0]);
+assert.throws(() => vm.loadProgram([0x00, 0xff], 0xffff), /doesn't fit/);
+vm.setRegister(3, 0x1234);
+assert.equal(vm.register(3), 0x1234);
+assert.equal(vm.register(11), vm.registers().usp);
+assert.throws(() => vm.register(12), /no register/);
+
+console.log("wasm: all tests passed");